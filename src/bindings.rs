@@ -0,0 +1,473 @@
+use crate::{
+    graze::SpecialAction,
+    player::{FireAction, MoveAction},
+    state::{spawn_screen, AppState, Screen},
+    storage,
+    weapon::WeaponAction,
+};
+use bevy::{ecs::system::EntityCommands, prelude::*};
+use leafwing_input_manager::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::{collections::BTreeMap, fmt};
+
+/// Loads the player's controls from `BINDINGS_FILE`, and lets them be rebound on the controls
+/// screen, opened from the title screen.
+pub struct BindingsPlugin;
+
+impl Plugin for BindingsPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Bindings>()
+            .init_resource::<Rebinding>()
+            .add_systems(Startup, load_bindings)
+            .add_systems(
+                OnEnter(AppState::Controls),
+                (reset_rebinding, spawn_controls_screen, spawn_control_rows).chain(),
+            )
+            .add_systems(OnExit(AppState::Controls), save_bindings)
+            .add_systems(
+                Update,
+                (handle_controls_input, update_controls_screen)
+                    .chain()
+                    .run_if(in_state(AppState::Controls)),
+            );
+    }
+}
+
+/// Everything the player can rebind.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub enum Control {
+    Left,
+    Right,
+    Up,
+    Down,
+    Focus,
+    Dash,
+    Fire,
+    Special,
+    PreviousWeapon,
+    NextWeapon,
+}
+
+impl Control {
+    const ALL: [Self; 10] = [
+        Self::Left,
+        Self::Right,
+        Self::Up,
+        Self::Down,
+        Self::Focus,
+        Self::Dash,
+        Self::Fire,
+        Self::Special,
+        Self::PreviousWeapon,
+        Self::NextWeapon,
+    ];
+
+    fn name(self) -> &'static str {
+        match self {
+            Self::Left => "Left",
+            Self::Right => "Right",
+            Self::Up => "Up",
+            Self::Down => "Down",
+            Self::Focus => "Focus",
+            Self::Dash => "Dash",
+            Self::Fire => "Fire",
+            Self::Special => "Special",
+            Self::PreviousWeapon => "Previous weapon",
+            Self::NextWeapon => "Next weapon",
+        }
+    }
+}
+
+/// A button a control can be bound to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Binding {
+    Key(KeyCode),
+    Mouse(MouseButton),
+    Gamepad(GamepadButtonType),
+}
+
+impl Binding {
+    /// Every control has at most one keyboard or mouse binding, and one gamepad binding.
+    fn gamepad(&self) -> bool {
+        matches!(self, Self::Gamepad(_))
+    }
+}
+
+impl fmt::Display for Binding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Key(key) => {
+                let name = format!("{key:?}");
+                f.write_str(name.strip_prefix("Key").unwrap_or(&name))
+            }
+            Self::Mouse(button) => write!(f, "Mouse {button:?}"),
+            Self::Gamepad(button) => write!(f, "Pad {button:?}"),
+        }
+    }
+}
+
+/// The devices a player is controlled with, so that players can share the bindings in co-op.
+#[derive(Component, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Devices {
+    All,
+    KeyboardMouse,
+    Gamepad,
+}
+
+impl Devices {
+    pub fn keyboard_mouse(self) -> bool {
+        self != Self::Gamepad
+    }
+
+    pub fn gamepad(self) -> bool {
+        self != Self::KeyboardMouse
+    }
+
+    fn includes(self, binding: &Binding) -> bool {
+        if binding.gamepad() {
+            self.gamepad()
+        } else {
+            self.keyboard_mouse()
+        }
+    }
+}
+
+/// The buttons bound to every control, saved to `BINDINGS_FILE` in the data directory.
+///
+/// The sticks and the menu keys (Esc, and Start and Select on a gamepad) can't be rebound.
+#[derive(Resource, Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Bindings(BTreeMap<Control, Vec<Binding>>);
+
+const BINDINGS_FILE: &str = "bindings.ron";
+
+impl Default for Bindings {
+    fn default() -> Self {
+        use Binding::*;
+
+        Self(BTreeMap::from([
+            (Control::Left, vec![Key(KeyCode::KeyA)]),
+            (Control::Right, vec![Key(KeyCode::KeyD)]),
+            (Control::Up, vec![Key(KeyCode::KeyW)]),
+            (Control::Down, vec![Key(KeyCode::KeyS)]),
+            (
+                Control::Focus,
+                vec![
+                    Key(KeyCode::ShiftLeft),
+                    Gamepad(GamepadButtonType::LeftTrigger),
+                ],
+            ),
+            (
+                Control::Dash,
+                vec![Key(KeyCode::Space), Gamepad(GamepadButtonType::South)],
+            ),
+            (
+                Control::Fire,
+                vec![
+                    Mouse(MouseButton::Left),
+                    Gamepad(GamepadButtonType::RightTrigger2),
+                ],
+            ),
+            (
+                Control::Special,
+                vec![
+                    Mouse(MouseButton::Right),
+                    Gamepad(GamepadButtonType::LeftTrigger2),
+                ],
+            ),
+            (
+                Control::PreviousWeapon,
+                vec![Key(KeyCode::KeyQ), Gamepad(GamepadButtonType::DPadLeft)],
+            ),
+            (
+                Control::NextWeapon,
+                vec![Key(KeyCode::KeyE), Gamepad(GamepadButtonType::DPadRight)],
+            ),
+        ]))
+    }
+}
+
+impl Bindings {
+    fn get(&self, control: Control) -> &[Binding] {
+        self.0.get(&control).map_or(&[], Vec::as_slice)
+    }
+
+    /// The other control `binding` is already bound to, if any.
+    fn conflict(&self, control: Control, binding: Binding) -> Option<Control> {
+        Control::ALL
+            .into_iter()
+            .find(|other| *other != control && self.get(*other).contains(&binding))
+    }
+
+    /// Binds `binding` to `control`, replacing its binding of the same kind.
+    ///
+    /// Fails with the control it conflicts with, if any, without changing anything.
+    fn rebind(&mut self, control: Control, binding: Binding) -> Result<(), Control> {
+        if let Some(other) = self.conflict(control, binding) {
+            return Err(other);
+        }
+
+        let bindings = self.0.entry(control).or_default();
+        bindings.retain(|bound| bound.gamepad() != binding.gamepad());
+        bindings.push(binding);
+        Ok(())
+    }
+
+    /// Builds the input map of the controls that `action` maps to an action of `A`, with only the
+    /// bindings of `devices`.
+    fn input_map<A: Actionlike>(
+        &self,
+        devices: Devices,
+        action: impl Fn(Control) -> Option<A>,
+    ) -> InputMap<A> {
+        let mut input_map = InputMap::default();
+        for control in Control::ALL {
+            let Some(action) = action(control) else {
+                continue;
+            };
+
+            for binding in self.get(control) {
+                if !devices.includes(binding) {
+                    continue;
+                }
+
+                match *binding {
+                    Binding::Key(key) => input_map.insert(action.clone(), key),
+                    Binding::Mouse(button) => input_map.insert(action.clone(), button),
+                    Binding::Gamepad(button) => input_map.insert(action.clone(), button),
+                };
+            }
+        }
+
+        input_map
+    }
+
+    /// Gives `player` the input maps of every rebindable action, bound to `devices`.
+    pub fn insert_input_maps(&self, player: &mut EntityCommands, devices: Devices) {
+        let move_input_map = self.input_map(devices, |control| match control {
+            Control::Left => Some(MoveAction::Left),
+            Control::Right => Some(MoveAction::Right),
+            Control::Up => Some(MoveAction::Up),
+            Control::Down => Some(MoveAction::Down),
+            Control::Focus => Some(MoveAction::Focus),
+            Control::Dash => Some(MoveAction::Dash),
+            _ => None,
+        });
+        let fire_input_map = self.input_map(devices, |control| {
+            (control == Control::Fire).then_some(FireAction)
+        });
+        let weapon_input_map = self.input_map(devices, |control| match control {
+            Control::PreviousWeapon => Some(WeaponAction::Previous),
+            Control::NextWeapon => Some(WeaponAction::Next),
+            _ => None,
+        });
+        let special_input_map = self.input_map(devices, |control| {
+            (control == Control::Special).then_some(SpecialAction)
+        });
+
+        player.insert((
+            InputManagerBundle::with_map(move_input_map),
+            InputManagerBundle::with_map(fire_input_map),
+            InputManagerBundle::with_map(weapon_input_map),
+            InputManagerBundle::with_map(special_input_map),
+        ));
+    }
+}
+
+fn load_bindings(mut bindings: ResMut<Bindings>) {
+    let loaded = match storage::load::<Bindings>(BINDINGS_FILE) {
+        Ok(Some(loaded)) => loaded,
+        Ok(None) => return,
+        Err(err) => {
+            warn!("Could not load bindings, using the defaults: {err}");
+            return;
+        }
+    };
+
+    // Controls added since the file was saved keep their defaults.
+    bindings.0.extend(loaded.0);
+
+    for control in Control::ALL {
+        for binding in bindings.get(control) {
+            if let Some(other) = bindings.conflict(control, *binding) {
+                if other > control {
+                    warn!(
+                        "{binding} is bound to both {} and {}",
+                        control.name(),
+                        other.name()
+                    );
+                }
+            }
+        }
+    }
+}
+
+fn save_bindings(bindings: Res<Bindings>) {
+    if let Err(err) = storage::save(BINDINGS_FILE, &*bindings) {
+        warn!("Could not save bindings: {err}");
+    }
+}
+
+/// State of the controls screen.
+#[derive(Resource, Debug, Default)]
+struct Rebinding {
+    /// Index in `Control::ALL`.
+    selected: usize,
+    /// Waiting for the next button to bind to the selected control.
+    capturing: bool,
+    message: String,
+}
+
+#[derive(Component)]
+struct ControlRow(Control);
+
+#[derive(Component)]
+struct RebindingMessage;
+
+fn reset_rebinding(mut rebinding: ResMut<Rebinding>) {
+    *rebinding = Rebinding::default();
+}
+
+fn spawn_controls_screen(mut commands: Commands) {
+    spawn_screen(
+        &mut commands,
+        AppState::Controls,
+        "Controls",
+        "Enter to rebind, Backspace to reset to defaults, Esc to return",
+    );
+}
+
+/// Lists the controls below the subtitle, filled in by `update_controls_screen`.
+fn spawn_control_rows(mut commands: Commands, screens: Query<(Entity, &Screen)>) {
+    let Some((screen, _)) = screens
+        .iter()
+        .find(|(_, screen)| screen.0 == AppState::Controls)
+    else {
+        return;
+    };
+
+    let style = |font_size| TextStyle {
+        font_size,
+        ..Default::default()
+    };
+
+    commands.entity(screen).with_children(|parent| {
+        for control in Control::ALL {
+            parent.spawn((
+                ControlRow(control),
+                TextBundle::from_section("", style(28.)),
+            ));
+        }
+        parent.spawn((RebindingMessage, TextBundle::from_section("", style(24.))));
+    });
+}
+
+fn update_controls_screen(
+    bindings: Res<Bindings>,
+    rebinding: Res<Rebinding>,
+    mut rows: Query<(&ControlRow, &mut Text), Without<RebindingMessage>>,
+    mut message: Query<&mut Text, With<RebindingMessage>>,
+) {
+    if !bindings.is_changed() && !rebinding.is_changed() {
+        return;
+    }
+
+    for (row, mut text) in rows.iter_mut() {
+        let selected = Control::ALL[rebinding.selected] == row.0;
+        let bound = if selected && rebinding.capturing {
+            "press a key or button".to_string()
+        } else {
+            bindings
+                .get(row.0)
+                .iter()
+                .map(Binding::to_string)
+                .collect::<Vec<_>>()
+                .join(" / ")
+        };
+
+        let section = &mut text.sections[0];
+        section.value = format!("{}: {bound}", row.0.name());
+        section.style.color = if selected {
+            Color::srgb(1., 0.85, 0.2)
+        } else {
+            Color::WHITE
+        };
+    }
+
+    if let Ok(mut text) = message.get_single_mut() {
+        text.sections[0].value.clone_from(&rebinding.message);
+    }
+}
+
+fn handle_controls_input(
+    keys: Res<ButtonInput<KeyCode>>,
+    mouse_buttons: Res<ButtonInput<MouseButton>>,
+    gamepad_buttons: Res<ButtonInput<GamepadButton>>,
+    mut bindings: ResMut<Bindings>,
+    mut rebinding: ResMut<Rebinding>,
+    mut next_state: ResMut<NextState<AppState>>,
+) {
+    let pad_buttons = || {
+        gamepad_buttons
+            .get_just_pressed()
+            .map(|button| button.button_type)
+    };
+    let pad = |button_type| pad_buttons().any(|pressed| pressed == button_type);
+    let back = keys.just_pressed(KeyCode::Escape) || pad(GamepadButtonType::Select);
+
+    if rebinding.capturing {
+        if back {
+            rebinding.capturing = false;
+            rebinding.message.clear();
+            return;
+        }
+
+        let binding = keys
+            .get_just_pressed()
+            .next()
+            .copied()
+            .map(Binding::Key)
+            .or_else(|| {
+                mouse_buttons
+                    .get_just_pressed()
+                    .next()
+                    .copied()
+                    .map(Binding::Mouse)
+            })
+            .or_else(|| {
+                pad_buttons()
+                    .find(|button| *button != GamepadButtonType::Start)
+                    .map(Binding::Gamepad)
+            });
+        let Some(binding) = binding else {
+            return;
+        };
+
+        let control = Control::ALL[rebinding.selected];
+        rebinding.message = match bindings.rebind(control, binding) {
+            Ok(()) => {
+                rebinding.capturing = false;
+                String::new()
+            }
+            Err(other) => format!("{binding} is already bound to {}", other.name()),
+        };
+        return;
+    }
+
+    let len = Control::ALL.len();
+    if keys.just_pressed(KeyCode::ArrowUp) || pad(GamepadButtonType::DPadUp) {
+        rebinding.selected = (rebinding.selected + len - 1) % len;
+    }
+    if keys.just_pressed(KeyCode::ArrowDown) || pad(GamepadButtonType::DPadDown) {
+        rebinding.selected = (rebinding.selected + 1) % len;
+    }
+
+    if keys.just_pressed(KeyCode::Enter) || pad(GamepadButtonType::South) {
+        rebinding.capturing = true;
+        rebinding.message.clear();
+    } else if keys.just_pressed(KeyCode::Backspace) || pad(GamepadButtonType::North) {
+        *bindings = Bindings::default();
+        rebinding.message = "Reset to defaults".to_string();
+    } else if back {
+        next_state.set(AppState::Title);
+    }
+}
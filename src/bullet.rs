@@ -1,26 +1,45 @@
 use crate::{
     build_mesh,
-    camera::PlayerCamera,
-    enemy::{Enemy, ENEMY_RADIUS},
-    player::Player,
-    Health, Velocity,
+    camera::{PlayerCamera, ScreenShake},
+    collision::{Collider, ColliderShape, CollisionStarted, Contacts, Layers},
+    dash::Dash,
+    enemy::{Enemy, EnemyKilled},
+    interpolation::Interpolated,
+    pickup::Modifiers,
+    player::{Dead, Invulnerable, Player, PlayerRespawned},
+    ron_asset::{RonAsset, RonAssetApp},
+    state::{InGame, RequiredAssets},
+    GameSet, Health, Velocity,
 };
 use bevy::{
-    math::NormedVectorSpace, prelude::*, render::view::visibility, sprite::Mesh2dHandle,
-    window::PrimaryWindow,
+    asset::LoadedFolder, prelude::*, sprite::Mesh2dHandle, utils::HashMap, window::PrimaryWindow,
 };
-use std::collections::HashMap;
+use serde::Deserialize;
 
 pub struct BulletPlugin;
 
 impl Plugin for BulletPlugin {
     fn build(&self, app: &mut App) {
-        app.add_event::<SpawnBullet>()
+        app.init_ron_asset::<BulletDef>()
+            .add_event::<SpawnBullet>()
             .add_event::<DespawnBullet>()
             .add_systems(Startup, init_bullets)
-            .add_systems(PreUpdate, spawn_bullets)
-            .add_systems(Update, bullet_hit_enemy)
-            .add_systems(PostUpdate, (cull_bullets, despawn_bullets));
+            .add_systems(PreUpdate, load_bullet_metas)
+            .add_systems(
+                FixedUpdate,
+                (
+                    update_bullet_behaviors.in_set(GameSet::Control),
+                    spawn_bullets.in_set(GameSet::Spawn),
+                    (bullet_hit_enemy, bullet_hit_player).in_set(GameSet::Damage),
+                    (
+                        (cull_bullets, expire_bullets, clear_bullets_on_respawn),
+                        despawn_bullets,
+                    )
+                        .chain()
+                        .in_set(GameSet::Cleanup),
+                ),
+            )
+            .add_systems(OnExit(InGame), clear_bullets);
     }
 }
 
@@ -28,186 +47,530 @@ impl Plugin for BulletPlugin {
 ///
 /// If you want to destroy a bullet, use the `DespawnBullet` event.
 #[derive(Component)]
-pub struct Bullet;
+pub struct Bullet {
+    pub faction: Faction,
+    /// The player that fired it, credited for its kills.
+    pub owner: Option<Entity>,
+    pub damage: f32,
+    pub graze: GrazeState,
+    lifetime: Timer,
+}
+
+/// How far along an enemy bullet is in passing by a player, see `graze::detect_grazes`.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum GrazeState {
+    #[default]
+    Away,
+    /// Within the graze radius of this player.
+    Near(Entity),
+    /// Left the graze radius without hitting, it won't count again.
+    Grazed,
+}
+
+/// Who a bullet, or the entity it may hit, belongs to.
+///
+/// Bullets never damage entities of their own faction.
+#[derive(Component, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Faction {
+    Player,
+    Enemy,
+    /// Hazards that damage everyone.
+    Neutral,
+}
+
+impl Faction {
+    pub fn can_damage(self, target: Faction) -> bool {
+        self != target
+    }
+
+    /// Layers and mask of a bullet fired by this faction.
+    fn bullet_layers(self) -> (Layers, Layers) {
+        match self {
+            Self::Player => (Layers::PLAYER_BULLET, Layers::ENEMY),
+            Self::Enemy => (Layers::ENEMY_BULLET, Layers::PLAYER),
+            Self::Neutral => (Layers::NEUTRAL_BULLET, Layers::PLAYER | Layers::ENEMY),
+        }
+    }
+}
 
 #[derive(Event)]
 pub struct SpawnBullet {
     pub ty: BulletType,
+    pub faction: Faction,
+    pub owner: Option<Entity>,
     pub position: Vec3,
     /// Does not have to be normalized.
     pub direction: Vec3,
 }
 
 #[derive(Event)]
-pub struct DespawnBullet(Entity);
+pub struct DespawnBullet(pub Entity);
 
-#[derive(Component, Debug, PartialEq, Eq, Hash)]
-pub enum BulletType {
-    Ball,
-}
+/// Identifies a bullet declared in `assets/bullets/{name}.bullet.ron`.
+#[derive(Component, Debug, Clone, PartialEq, Eq, Hash)]
+pub struct BulletType(pub Handle<BulletDef>);
 
-fn init_bullets(mut commands: Commands, mut meshes: ResMut<Assets<Mesh>>) {
-    let mut bullet_map = HashMap::default();
+impl BulletType {
+    pub fn named(asset_server: &AssetServer, name: &str) -> Self {
+        Self(asset_server.load(format!("bullets/{name}.bullet.ron")))
+    }
+}
 
-    let ball_meta = BulletMeta {
-        mesh: meshes.add(build_mesh(10., 5)).into(),
-        speed: 1000.,
-    };
+/// A bullet type as written by designers in a `.bullet.ron` file.
+#[derive(Asset, TypePath, Debug, Deserialize)]
+pub struct BulletDef {
+    pub radius: f32,
+    pub vertices: usize,
+    #[serde(default)]
+    pub color: Srgba,
+    pub speed: f32,
+    pub damage: f32,
+    /// Seconds before the bullet despawns on its own.
+    pub lifetime: f32,
+    /// Number of bullets spawned up front so that firing doesn't have to spawn entities.
+    #[serde(default = "default_pool_size")]
+    pub pool_size: usize,
+    /// Defaults to a circle of `radius`.
+    #[serde(default)]
+    pub collider: Option<ColliderShape>,
+    #[serde(default)]
+    pub behaviors: Vec<BulletBehavior>,
+}
 
-    for _ in 0..10 {
-        spawn_bullet(
-            &mut commands,
-            &ball_meta,
-            BulletType::Ball,
-            Some(InactiveBullet),
-        );
+impl BulletDef {
+    fn collider(&self) -> ColliderShape {
+        self.collider.clone().unwrap_or(ColliderShape::Circle {
+            radius: self.radius,
+        })
     }
+}
 
-    bullet_map.insert(BulletType::Ball, ball_meta);
+fn default_pool_size() -> usize {
+    128
+}
 
-    commands.insert_resource(BulletMetas(bullet_map));
+/// Modifies a bullet's velocity every frame.
+#[derive(Debug, Clone, Deserialize)]
+pub enum BulletBehavior {
+    /// Changes speed by `rate` units per second, clamped between 0 and `max_speed`.
+    Accelerate { rate: f32, max_speed: f32 },
+    /// Rotates the heading by `angular_velocity` radians per second.
+    Curve { angular_velocity: f32 },
+    /// Turns towards the nearest damageable target by at most `turn_rate` radians per second.
+    Homing { turn_rate: f32 },
 }
 
-fn spawn_bullet(
-    commands: &mut Commands,
-    meta: &BulletMeta,
-    ty: BulletType,
-    inactive: Option<InactiveBullet>,
+impl RonAsset for BulletDef {
+    const EXTENSIONS: &'static [&'static str] = &["bullet.ron"];
+}
+
+/// Keeps every bullet definition loaded so that their pools exist before anything fires.
+#[derive(Resource)]
+struct BulletFolder(#[allow(dead_code)] Handle<LoadedFolder>);
+
+fn init_bullets(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    mut required: ResMut<RequiredAssets>,
 ) {
-    let visibility = if inactive.is_none() {
-        Visibility::Visible
-    } else {
-        Visibility::Hidden
-    };
+    let folder = asset_server.load_folder("bullets");
+    required.add(folder.clone());
+    commands.insert_resource(BulletFolder(folder));
+    commands.insert_resource(BulletMetas(HashMap::default()));
+}
+
+/// Builds the `BulletMeta` and pool of every newly loaded `BulletDef`, and updates them in place
+/// when a definition is hot reloaded.
+fn load_bullet_metas(
+    mut commands: Commands,
+    mut events: EventReader<AssetEvent<BulletDef>>,
+    mut defs: ResMut<Assets<BulletDef>>,
+    mut metas: ResMut<BulletMetas>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+) {
+    for event in events.read() {
+        match event {
+            AssetEvent::Added { id } => {
+                let Some(handle) = defs.get_strong_handle(*id) else {
+                    continue;
+                };
+                let def = defs.get(*id).unwrap();
+
+                let meta = BulletMeta {
+                    mesh: meshes.add(build_mesh(def.radius, def.vertices)).into(),
+                    material: materials.add(Color::from(def.color)),
+                    speed: def.speed,
+                    damage: def.damage,
+                    lifetime: def.lifetime,
+                    behaviors: def.behaviors.clone(),
+                    collider: def.collider(),
+                };
 
-    let mut e = commands.spawn((
+                for _ in 0..def.pool_size {
+                    spawn_bullet(&mut commands, &meta, BulletType(handle.clone()));
+                }
+
+                metas.0.insert(*id, meta);
+            }
+            AssetEvent::Modified { id } => {
+                let (Some(def), Some(meta)) = (defs.get(*id), metas.0.get_mut(id)) else {
+                    continue;
+                };
+
+                meshes.insert(&meta.mesh.0, build_mesh(def.radius, def.vertices));
+                materials.insert(&meta.material, Color::from(def.color).into());
+                meta.speed = def.speed;
+                meta.damage = def.damage;
+                meta.lifetime = def.lifetime;
+                meta.behaviors = def.behaviors.clone();
+                meta.collider = def.collider();
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Spawns an inactive bullet into the pool.
+fn spawn_bullet(commands: &mut Commands, meta: &BulletMeta, ty: BulletType) {
+    commands.spawn((
         ColorMesh2dBundle {
             mesh: meta.mesh.clone(),
-            visibility,
+            material: meta.material.clone(),
+            visibility: Visibility::Hidden,
             ..Default::default()
         },
         Velocity::default(),
-        Bullet,
+        InactiveBullet,
         ty,
     ));
-
-    if inactive.is_some() {
-        e.insert(InactiveBullet);
-    }
 }
 
+/// A pooled bullet waiting to be fired. It stands still and isn't interpolated.
 #[derive(Component)]
-struct InactiveBullet;
+pub struct InactiveBullet;
 
 #[derive(Resource)]
-struct BulletMetas(HashMap<BulletType, BulletMeta>);
+struct BulletMetas(HashMap<AssetId<BulletDef>, BulletMeta>);
 
 struct BulletMeta {
     mesh: Mesh2dHandle,
+    material: Handle<ColorMaterial>,
     speed: f32,
+    damage: f32,
+    lifetime: f32,
+    behaviors: Vec<BulletBehavior>,
+    collider: ColliderShape,
 }
 
 fn spawn_bullets(
     mut commands: Commands,
     mut reader: EventReader<SpawnBullet>,
     meta: Res<BulletMetas>,
-    mut balls: Query<
-        (Entity, &mut Transform, &mut Velocity, &mut Visibility),
+    mut inactive: Query<
+        (
+            Entity,
+            &BulletType,
+            &mut Transform,
+            &mut Velocity,
+            &mut Visibility,
+        ),
         With<InactiveBullet>,
     >,
 ) {
+    if reader.is_empty() {
+        return;
+    }
+
+    // Commands are deferred, so the pools have to be collected up front for all events in this frame.
+    let mut pools: HashMap<AssetId<BulletDef>, Vec<Entity>> = HashMap::default();
+    for (entity, ty, ..) in inactive.iter() {
+        pools.entry(ty.0.id()).or_default().push(entity);
+    }
+
     for bullet in reader.read() {
-        let speed = meta.0.get(&bullet.ty).unwrap().speed;
-        let bullet_velocity = bullet.direction.normalize_or_zero() * speed;
-
-        match bullet.ty {
-            BulletType::Ball => {
-                if let Some((e, mut t, mut v, mut vis)) = balls.iter_mut().next() {
-                    t.translation = bullet.position;
-                    v.0 = bullet_velocity;
-                    *vis = Visibility::Visible;
-                    commands.entity(e).remove::<InactiveBullet>().insert(Bullet);
-                } else {
-                    warn!(
-                        "Spawning BulletType[`{:?}`]. Maybe increase initial buffer?",
-                        bullet.ty
-                    );
-
-                    commands.spawn((
-                        ColorMesh2dBundle {
-                            mesh: meta.0.get(&BulletType::Ball).unwrap().mesh.clone(),
-                            // visibility: Visibility::Hidden,
-                            transform: Transform::from_translation(bullet.position),
-                            ..Default::default()
-                        },
-                        // InactiveBullet,
-                        // Velocity::default(),
-                        Velocity(bullet_velocity),
-                        Bullet,
-                        BulletType::Ball,
-                    ));
+        let Some(bullet_meta) = meta.0.get(&bullet.ty.0.id()) else {
+            warn!("BulletType[`{:?}`] is not loaded yet.", bullet.ty.0.path());
+            continue;
+        };
+
+        let bullet_velocity = bullet.direction.normalize_or_zero() * bullet_meta.speed;
+        let component = Bullet {
+            faction: bullet.faction,
+            owner: bullet.owner,
+            damage: bullet_meta.damage,
+            graze: GrazeState::Away,
+            lifetime: Timer::from_seconds(bullet_meta.lifetime, TimerMode::Once),
+        };
+        let (layers, mask) = bullet.faction.bullet_layers();
+        let collider = Collider::new(bullet_meta.collider.clone(), layers, mask);
+        let rotation = Quat::from_rotation_z(bullet_velocity.truncate().to_angle());
+
+        if let Some(e) = pools.get_mut(&bullet.ty.0.id()).and_then(Vec::pop) {
+            let (_, _, mut t, mut v, mut vis) = inactive.get_mut(e).unwrap();
+            t.translation = bullet.position;
+            t.rotation = rotation;
+            v.0 = bullet_velocity;
+            *vis = Visibility::Visible;
+            commands.entity(e).remove::<InactiveBullet>().insert((
+                component,
+                collider,
+                Interpolated::new(&t),
+            ));
+        } else {
+            warn!(
+                "Spawning BulletType[`{:?}`]. Maybe increase its pool_size?",
+                bullet.ty.0.path()
+            );
+
+            let transform = Transform::from_translation(bullet.position).with_rotation(rotation);
+            commands.spawn((
+                ColorMesh2dBundle {
+                    mesh: bullet_meta.mesh.clone(),
+                    material: bullet_meta.material.clone(),
+                    transform,
+                    ..Default::default()
+                },
+                Velocity(bullet_velocity),
+                Interpolated::new(&transform),
+                component,
+                collider,
+                bullet.ty.clone(),
+            ));
+        }
+    }
+}
+
+fn update_bullet_behaviors(
+    mut bullets: Query<(&mut Transform, &Bullet, &BulletType, &mut Velocity)>,
+    targets: Query<(&Transform, &Faction), Without<Bullet>>,
+    metas: Res<BulletMetas>,
+    time: Res<Time>,
+) {
+    let dt = time.delta_seconds();
+
+    for (mut transform, bullet, ty, mut velocity) in bullets.iter_mut() {
+        let Some(meta) = metas.0.get(&ty.0.id()) else {
+            continue;
+        };
+
+        if meta.behaviors.is_empty() {
+            continue;
+        }
+
+        for behavior in meta.behaviors.iter() {
+            match *behavior {
+                BulletBehavior::Accelerate { rate, max_speed } => {
+                    let speed = (velocity.0.length() + rate * dt).clamp(0., max_speed);
+                    velocity.0 = velocity.0.normalize_or_zero() * speed;
+                }
+                BulletBehavior::Curve { angular_velocity } => {
+                    velocity.0 = Quat::from_rotation_z(angular_velocity * dt) * velocity.0;
+                }
+                BulletBehavior::Homing { turn_rate } => {
+                    let position = transform.translation;
+                    let Some(target) = targets
+                        .iter()
+                        .filter(|(_, faction)| bullet.faction.can_damage(**faction))
+                        .map(|(target, _)| target.translation)
+                        .min_by(|a, b| {
+                            a.distance_squared(position)
+                                .total_cmp(&b.distance_squared(position))
+                        })
+                    else {
+                        continue;
+                    };
+
+                    let heading = velocity.0.truncate();
+                    let turn = heading
+                        .angle_between((target - position).truncate())
+                        .clamp(-turn_rate * dt, turn_rate * dt);
+                    velocity.0 = Vec2::from_angle(turn).rotate(heading).extend(0.);
                 }
             }
         }
+
+        if let Some(heading) = velocity.0.truncate().try_normalize() {
+            transform.rotation = Quat::from_rotation_z(heading.to_angle());
+        }
     }
 }
 
+fn expire_bullets(
+    mut bullets: Query<(Entity, &mut Bullet)>,
+    time: Res<Time>,
+    mut writer: EventWriter<DespawnBullet>,
+) {
+    for (entity, mut bullet) in bullets.iter_mut() {
+        if bullet.lifetime.tick(time.delta()).finished() {
+            writer.send(DespawnBullet(entity));
+        }
+    }
+}
+
+/// Hides an active bullet, stops it and returns it to its pool.
+fn deactivate_bullet(
+    commands: &mut Commands,
+    bullet: Entity,
+    visibility: &mut Visibility,
+    velocity: &mut Velocity,
+) {
+    *visibility = Visibility::Hidden;
+    velocity.0 = Vec3::ZERO;
+    commands
+        .entity(bullet)
+        .remove::<(Bullet, Collider, Interpolated)>()
+        .insert(InactiveBullet);
+}
+
 fn despawn_bullets(
     mut commands: Commands,
-    mut bullets: Query<&mut Visibility, With<Bullet>>,
+    mut bullets: Query<(&mut Visibility, &mut Velocity), With<Bullet>>,
     mut reader: EventReader<DespawnBullet>,
 ) {
     for DespawnBullet(bullet) in reader.read() {
-        if let Ok(mut vis) = bullets.get_mut(*bullet) {
-            *vis = Visibility::Hidden;
-            commands
-                .entity(*bullet)
-                .remove::<Bullet>()
-                .insert(InactiveBullet);
+        if let Ok((mut vis, mut velocity)) = bullets.get_mut(*bullet) {
+            deactivate_bullet(&mut commands, *bullet, &mut vis, &mut velocity);
         }
     }
 }
 
-fn cull_bullets(
+/// Returns every bullet of the run to its pool, so that the next run starts with full pools.
+fn clear_bullets(
     mut commands: Commands,
-    mut bullets: Query<(Entity, &Transform, &mut Visibility), With<Bullet>>,
+    mut bullets: Query<(Entity, &mut Visibility, &mut Velocity), With<Bullet>>,
+    mut spawns: ResMut<Events<SpawnBullet>>,
+    mut despawns: ResMut<Events<DespawnBullet>>,
+) {
+    for (bullet, mut vis, mut velocity) in bullets.iter_mut() {
+        deactivate_bullet(&mut commands, bullet, &mut vis, &mut velocity);
+    }
+
+    spawns.clear();
+    despawns.clear();
+}
+
+/// Bullets are culled at the window's width from every player, or this without a window.
+const HEADLESS_CULL_DISTANCE: f32 = 1920.;
+
+fn cull_bullets(
+    bullets: Query<(Entity, &Transform), With<Bullet>>,
     player: Query<&Transform, With<Player>>,
     window: Query<&Window, With<PrimaryWindow>>,
+    mut writer: EventWriter<DespawnBullet>,
 ) {
-    let player_transform = player.single();
-    let window = window.single();
-
-    for (bullet, bullet_transform, mut visibility) in bullets.iter_mut() {
-        if player_transform
-            .translation
-            .distance_squared(bullet_transform.translation)
-            > (window.resolution.physical_width() * window.resolution.physical_width()) as f32
-        {
-            commands.entity(bullet).insert(InactiveBullet);
-            *visibility = Visibility::Hidden;
+    if player.is_empty() {
+        return;
+    }
+    let cull_distance = window
+        .get_single()
+        .map(|window| window.resolution.physical_width() as f32)
+        .unwrap_or(HEADLESS_CULL_DISTANCE);
+
+    for (bullet, bullet_transform) in bullets.iter() {
+        if player.iter().all(|player_transform| {
+            player_transform
+                .translation
+                .distance_squared(bullet_transform.translation)
+                > cull_distance * cull_distance
+        }) {
+            writer.send(DespawnBullet(bullet));
+        }
+    }
+}
+
+/// Bullets within this distance of a respawning player are cleared, so they don't die again on arrival.
+const RESPAWN_CLEAR_RADIUS: f32 = 400.;
+
+fn clear_bullets_on_respawn(
+    mut reader: EventReader<PlayerRespawned>,
+    bullets: Query<(Entity, &Transform, &Bullet)>,
+    mut writer: EventWriter<DespawnBullet>,
+) {
+    for respawned in reader.read() {
+        for (entity, transform, bullet) in bullets.iter() {
+            if bullet.faction.can_damage(Faction::Player)
+                && transform.translation.distance_squared(respawned.position)
+                    < RESPAWN_CLEAR_RADIUS * RESPAWN_CLEAR_RADIUS
+            {
+                writer.send(DespawnBullet(entity));
+            }
         }
     }
 }
 
 fn bullet_hit_enemy(
-    mut commands: Commands,
-    bullets: Query<(Entity, &Transform), With<Bullet>>,
-    mut enemies: Query<(&Transform, &mut Health), With<Enemy>>,
+    mut reader: EventReader<CollisionStarted>,
+    bullets: Query<&Bullet>,
+    mut enemies: Query<(&mut Health, &Faction, &Transform), With<Enemy>>,
     mut player_camera: ResMut<PlayerCamera>,
-    time: Res<Time>,
     mut writer: EventWriter<DespawnBullet>,
+    mut killed: EventWriter<EnemyKilled>,
 ) {
-    for (enemy, mut health) in enemies.iter_mut() {
-        for (bullet, transform) in bullets.iter() {
-            let dist = enemy.translation.distance(transform.translation);
-            if dist < ENEMY_RADIUS {
-                writer.send(DespawnBullet(bullet));
-                health.current -= 1.;
-
-                if health.current <= 0.01 {
-                    player_camera.push_screen_shake_with(10., 0.2, time.elapsed_seconds());
-                }
+    for event in reader.read() {
+        for (entity, target) in event.pairs() {
+            let (Ok(bullet), Ok((mut health, faction, transform))) =
+                (bullets.get(entity), enemies.get_mut(target))
+            else {
+                continue;
+            };
+
+            if !bullet.faction.can_damage(*faction) {
+                continue;
+            }
+
+            writer.send(DespawnBullet(entity));
+            // Already killed by another bullet this step.
+            if health.current <= 0.01 {
+                continue;
+            }
+
+            health.current -= bullet.damage;
+
+            if health.current <= 0.01 {
+                player_camera.push_screen_shake(ScreenShake::new(0.5));
+                killed.send(EnemyKilled {
+                    enemy: target,
+                    position: transform.translation,
+                    by: bullet.owner,
+                });
             }
         }
     }
 }
+
+/// Reads every ongoing contact rather than `CollisionStarted`, so that a bullet overlapping a player
+/// who can't be hit yet still hits once they can.
+fn bullet_hit_player(
+    contacts: Res<Contacts>,
+    bullets: Query<(&Bullet, &Transform)>,
+    mut player: Query<
+        (&mut Health, &Faction, &Transform, &Modifiers, &Dash),
+        (With<Player>, Without<Invulnerable>, Without<Dead>),
+    >,
+    mut player_camera: ResMut<PlayerCamera>,
+    mut writer: EventWriter<DespawnBullet>,
+) {
+    for (entity, target) in contacts.pairs() {
+        let (Ok((bullet, bullet_transform)), Ok((mut health, faction, transform, modifiers, dash))) =
+            (bullets.get(entity), player.get_mut(target))
+        else {
+            continue;
+        };
+
+        // Dashing players pass through bullets.
+        if !bullet.faction.can_damage(*faction) || dash.active() {
+            continue;
+        }
+
+        writer.send(DespawnBullet(entity));
+        if modifiers.shielded() {
+            continue;
+        }
+
+        health.current -= bullet.damage;
+        // Knock the camera away from where the bullet hit.
+        let away = (transform.translation - bullet_transform.translation).truncate();
+        player_camera.push_screen_shake(ScreenShake::new(0.4).with_kick(away, 30.));
+    }
+}
@@ -1,9 +1,14 @@
-use bevy::prelude::*;
+use bevy::{prelude::*, transform::TransformSystem};
 use noisy_bevy::simplex_noise_2d;
-use rand::{Rng, SeedableRng};
-use std::f32::consts::TAU;
+use rand::Rng;
 
-use crate::Player;
+use crate::{
+    enemy::Enemy,
+    player::{Aim, Dead},
+    rng::GameRng,
+    state::{AppState, InGame},
+    Player, ARENA,
+};
 
 #[derive(Debug)]
 pub struct CameraPlugin;
@@ -11,7 +16,14 @@ pub struct CameraPlugin;
 impl Plugin for CameraPlugin {
     fn build(&self, app: &mut App) {
         app.insert_resource(PlayerCamera::default())
-            .add_systems(Startup, startup);
+            .add_systems(Startup, startup)
+            .add_systems(OnExit(InGame), reset_camera)
+            .add_systems(
+                PostUpdate,
+                update_camera
+                    .run_if(in_state(AppState::Playing))
+                    .before(TransformSystem::TransformPropagate),
+            );
         // .egui_resource::<ScreenShake>()
         // .insert_resource(ScreenShake {
         //     intensity: 42.0,
@@ -19,7 +31,6 @@ impl Plugin for CameraPlugin {
         //     start_time: 0.0,
         // })
         // .add_systems(Schedule::Update, shake_screen)
-        // .add_systems(PostUpdate, update_camera);
     }
 }
 
@@ -27,36 +38,55 @@ fn startup(mut commands: Commands) {
     commands.spawn((MainCamera, Camera2dBundle::default()));
 }
 
+/// Recenters the camera and drops any shake left over from the last run.
+fn reset_camera(
+    mut player_camera: ResMut<PlayerCamera>,
+    mut camera: Query<(&mut Transform, &mut OrthographicProjection), With<MainCamera>>,
+    mut rng: ResMut<GameRng>,
+) {
+    *player_camera = PlayerCamera {
+        bounds: player_camera.bounds,
+        // Start somewhere else in the noise so that shakes don't repeat between runs.
+        shake_time: rng.cosmetic.gen_range(0.0..1000.0),
+        ..Default::default()
+    };
+
+    if let Ok((mut camera, mut projection)) = camera.get_single_mut() {
+        camera.translation = camera.translation.with_x(0.).with_y(0.);
+        camera.rotation = Quat::IDENTITY;
+        projection.scale = 1.;
+    }
+}
+
 #[derive(Component)]
 pub struct MainCamera;
 
+/// Kept in view by the camera, which frames every target at once and zooms out to fit them.
+#[derive(Component, Debug, Default, Clone, Copy)]
+pub struct CameraTarget;
+
+/// A single impact, pushed to `PlayerCamera` and combined with every other active shake.
 #[derive(Resource, Default, Debug, Clone, Copy)]
 pub struct ScreenShake {
-    intensity: f32,
-    duration: f32,
-    start_time: f32,
-    x_offset: Vec2,
-    y_offset: Vec2,
+    /// From 0 to 1, added to the camera's trauma.
+    pub trauma: f32,
+    /// Offset added to the camera, which then recovers back to the follow point.
+    pub kick: Vec2,
 }
 
 impl ScreenShake {
-    pub fn new(intensity: f32, duration: f32, start_time: f32) -> Self {
-        let mut rng = rand::rngs::SmallRng::from_entropy();
-        let mut offset = move || {
-            Vec2::new(
-                rng.gen_range(-1000f32..1000f32),
-                rng.gen_range(-1000f32..1000f32),
-            )
-        };
-
+    pub fn new(trauma: f32) -> Self {
         Self {
-            intensity,
-            duration,
-            start_time,
-            x_offset: offset(),
-            y_offset: offset(),
+            trauma,
+            kick: Vec2::ZERO,
         }
     }
+
+    /// Also knocks the camera `distance` units along `direction`, e.g. away from an impact.
+    pub fn with_kick(mut self, direction: Vec2, distance: f32) -> Self {
+        self.kick = direction.normalize_or_zero() * distance;
+        self
+    }
 }
 
 // fn shake_screen(
@@ -81,41 +111,117 @@ impl ScreenShake {
 //     }
 // }
 
+/// Runs after all movement, in `PostUpdate`, so the camera never lags a frame behind the player.
+///
+/// Dead players are left out of the frame until they respawn.
+#[allow(clippy::too_many_arguments)]
 fn update_camera(
     mut player_camera: ResMut<PlayerCamera>,
-    player: Query<&Transform, With<Player>>,
-    mut camera: Query<&mut Transform, (With<MainCamera>, Without<Player>)>,
+    player: Query<(&Transform, &Aim), With<Player>>,
+    targets: Query<&Transform, (With<CameraTarget>, Without<Dead>)>,
+    enemies: Query<&Transform, With<Enemy>>,
+    mut camera: Query<
+        (&mut Transform, &mut OrthographicProjection),
+        (
+            With<MainCamera>,
+            Without<Player>,
+            Without<CameraTarget>,
+            Without<Enemy>,
+        ),
+    >,
     time: Res<Time>,
-    // mouse_position: Res<MousePosition>,
 ) {
-    let Ok(mut camera) = camera.get_single_mut() else {
+    let Ok((mut camera, mut projection)) = camera.get_single_mut() else {
         return;
     };
 
-    let Ok(player) = player.get_single() else {
+    let Some(frame) = targets
+        .iter()
+        .map(|target| target.translation.truncate())
+        .fold(None, |frame: Option<Rect>, point| {
+            Some(
+                frame.map_or(Rect::from_center_size(point, Vec2::ZERO), |frame| {
+                    frame.union_point(point)
+                }),
+            )
+        })
+    else {
         return;
     };
 
+    // Only lead with the mouse when there is a single player to lead from.
+    let lead = player
+        .get_single()
+        .ok()
+        .and_then(|(player, aim)| Some(player_camera.lead(player.translation.truncate(), aim.0?)));
+
+    // Size of the view at a zoom of 1.
+    let view = projection.area.size() / projection.scale;
+    let visible = Rect::from_center_size(
+        player_camera.follow_point.truncate(),
+        view * player_camera.zoom,
+    );
+    let enemies_on_screen = enemies
+        .iter()
+        .filter(|enemy| visible.contains(enemy.translation.truncate()))
+        .count();
+
     player_camera.apply_screen_shake(&time);
-    player_camera.follow_player(
-        player,
-        // vel,
-        &mut camera, //&mouse_position,
+    player_camera.update_zoom(frame, view, enemies_on_screen, &time);
+    player_camera.follow(
+        frame.center().extend(0.) + lead.unwrap_or_default().extend(0.),
         &time,
     );
+    player_camera.clamp_to_bounds(view);
+
+    // Keep the camera's depth, otherwise everything in front of z = 0 is clipped.
+    camera.translation = player_camera.translation().with_z(camera.translation.z);
+    camera.rotation = Quat::from_rotation_z(player_camera.shake_angle);
+    projection.scale = player_camera.zoom;
 }
 
 /// Handles main camera logic.
 #[derive(Resource)]
 pub struct PlayerCamera {
-    screen_shake: Vec<ScreenShake>,
     max_smooth_factor: f32,
     min_smooth_factor: f32,
     max_distance: f32,
     delta: f32,
+    /// Fraction of the distance from the player to the mouse that the camera leads by.
     lead_factor: f32,
+    max_lead: f32,
+    /// From 0 to 1. Shake strength is trauma squared, so small hits barely register while big
+    /// ones stack up quickly.
+    trauma: f32,
+    /// Trauma lost per second.
+    trauma_decay: f32,
+    /// Offset at full trauma.
+    max_shake_offset: f32,
+    /// Rotation in radians at full trauma.
+    max_shake_angle: f32,
+    /// How fast the shake noise is sampled.
+    shake_frequency: f32,
+    shake_time: f32,
+    kick: Vec2,
+    max_kick: f32,
+    /// How fast kicks recover, as an exponential rate per second.
+    kick_recovery: f32,
     shake_offset: Vec3,
+    shake_angle: f32,
     follow_point: Vec3,
+    /// Scale of the projection, above 1 is zoomed out.
+    zoom: f32,
+    min_zoom: f32,
+    max_zoom: f32,
+    /// Zoom added for each enemy on screen, up to `max_zoom_enemies`.
+    zoom_per_enemy: f32,
+    max_zoom_enemies: usize,
+    /// Space kept around the targets when framing them, in world units.
+    framing_margin: f32,
+    /// How fast the zoom eases towards its target, as an exponential rate per second.
+    zoom_speed: f32,
+    /// The camera never shows anything outside of these, unless the view is larger than them.
+    pub bounds: Option<Rect>,
     // noise: Noise,
 }
 
@@ -129,38 +235,46 @@ pub struct PlayerCamera {
 impl Default for PlayerCamera {
     fn default() -> Self {
         Self {
-            screen_shake: Vec::new(),
             max_smooth_factor: 1.0,
             min_smooth_factor: 1.0,
             max_distance: 100.0,
             delta: 10.0,
-            lead_factor: 1.0,
+            lead_factor: 0.25,
+            max_lead: 200.0,
+            trauma: 0.0,
+            trauma_decay: 1.5,
+            max_shake_offset: 40.0,
+            max_shake_angle: 0.05,
+            shake_frequency: 15.0,
+            shake_time: 0.0,
+            kick: Vec2::ZERO,
+            max_kick: 60.0,
+            kick_recovery: 10.0,
             follow_point: Vec3::new(0., 0., 0.),
             shake_offset: Vec3::ZERO,
+            shake_angle: 0.0,
+            zoom: 1.0,
+            min_zoom: 1.0,
+            max_zoom: 2.5,
+            zoom_per_enemy: 0.03,
+            max_zoom_enemies: 10,
+            framing_margin: 300.0,
+            zoom_speed: 2.0,
+            bounds: Some(ARENA),
             // noise: Noise(noise::OpenSimplex::new(1)),
         }
     }
 }
 
 impl PlayerCamera {
-    pub fn follow_player(
-        &mut self,
-        // velocity: &DirectionalVelocity,
-        player: &Transform,
-        camera: &mut Transform,
-        // mouse_position: &MousePosition,
-        dt: &Time,
-    ) {
-        // let direction: Vec3 = mouse_position.0.into();
-        // let offset = (direction - player.translation).normalize() * 100.;
-        let offset = 0.;
-
-        let target = player.translation + offset;
+    /// Lead towards where the player is aiming, so that more of the screen is in front of them.
+    fn lead(&self, player: Vec2, mouse: Vec2) -> Vec2 {
+        ((mouse - player) * self.lead_factor).clamp_length_max(self.max_lead)
+    }
 
+    pub fn follow(&mut self, target: Vec3, dt: &Time) {
         let distance_to_target = (target - self.follow_point).length();
 
-        let target: Vec3 = target; // Vec3::from(*velocity).normalize() + target;
-
         if distance_to_target < self.delta {
             self.follow_point = target;
         } else {
@@ -175,52 +289,57 @@ impl PlayerCamera {
             // Move the camera smoothly towards the target position
             self.follow_point += (target - self.follow_point) * smooth_factor * dt.delta_seconds();
         }
+    }
 
-        camera.translation = self.translation();
+    /// Eases the zoom out as the fight gets busier, and far enough to fit all of `frame`.
+    fn update_zoom(&mut self, frame: Rect, view: Vec2, enemies_on_screen: usize, dt: &Time) {
+        let busy = 1. + enemies_on_screen.min(self.max_zoom_enemies) as f32 * self.zoom_per_enemy;
+        let fit = ((frame.size() + 2. * self.framing_margin) / view.max(Vec2::ONE)).max_element();
+        let target = busy.max(fit).clamp(self.min_zoom, self.max_zoom);
+
+        self.zoom += (target - self.zoom) * (1. - (-self.zoom_speed * dt.delta_seconds()).exp());
     }
 
-    pub fn push_screen_shake(&mut self, shake: ScreenShake) {
-        self.screen_shake.push(shake);
+    /// Keeps the view inside `bounds`, or centered on them when it doesn't fit.
+    fn clamp_to_bounds(&mut self, view: Vec2) {
+        let Some(bounds) = self.bounds else {
+            return;
+        };
+
+        let half = view * self.zoom / 2.;
+        let clamp = |point: f32, min: f32, max: f32, half: f32| {
+            if max - min < half * 2. {
+                (min + max) / 2.
+            } else {
+                point.clamp(min + half, max - half)
+            }
+        };
+
+        self.follow_point.x = clamp(self.follow_point.x, bounds.min.x, bounds.max.x, half.x);
+        self.follow_point.y = clamp(self.follow_point.y, bounds.min.y, bounds.max.y, half.y);
     }
 
-    pub fn push_screen_shake_with(&mut self, intensity: f32, duration: f32, start_time: f32) {
-        self.screen_shake
-            .push(ScreenShake::new(intensity, duration, start_time));
+    /// Concurrent shakes stack: trauma adds up to 1 and kicks add up to `max_kick`.
+    pub fn push_screen_shake(&mut self, shake: ScreenShake) {
+        self.trauma = (self.trauma + shake.trauma).clamp(0., 1.);
+        self.kick = (self.kick + shake.kick).clamp_length_max(self.max_kick);
     }
 
     fn apply_screen_shake(&mut self, dt: &Time) {
-        for shake in self.screen_shake.iter_mut() {
-            let elapsed = dt.elapsed_seconds_wrapped() - shake.start_time;
-            if elapsed < shake.duration {
-                let remaining = 1.0 - (elapsed / shake.duration);
-                let current_intensity = shake.intensity * remaining;
-
-                // Sample the simplex space in a circle
-                let radius = 5.;
-
-                let x_noise = simplex_noise_2d(Vec2::new(
-                    shake.x_offset.x + (remaining * TAU).cos() * radius,
-                    shake.x_offset.y + (remaining * TAU).sin() * radius,
-                ));
-
-                let y_noise = simplex_noise_2d(Vec2::new(
-                    shake.y_offset.x + (remaining * TAU).cos() * radius,
-                    shake.y_offset.y + (remaining * TAU).sin() * radius,
-                ));
-
-                self.shake_offset = Vec3::new(
-                    x_noise as f32 * current_intensity,
-                    y_noise as f32 * current_intensity,
-                    0.0, // typically, we don't shake on the z-axis
-                );
-            } else {
-                // Shake finished
-                self.shake_offset = Vec3::new(0.0, 0.0, 0.0);
-                shake.start_time = 0.0;
-            }
-        }
+        let dt = dt.delta_seconds();
+        let shake = self.trauma * self.trauma;
+
+        // Each axis samples its own row of the noise, so they move independently.
+        self.shake_time += dt * self.shake_frequency;
+        let noise = |row: f32| simplex_noise_2d(Vec2::new(self.shake_time, row));
+
+        self.shake_offset = (Vec2::new(noise(0.), noise(100.)) * self.max_shake_offset * shake
+            + self.kick)
+            .extend(0.);
+        self.shake_angle = noise(200.) * self.max_shake_angle * shake;
 
-        self.screen_shake.retain(|s| s.start_time > 0.0);
+        self.trauma = (self.trauma - self.trauma_decay * dt).max(0.);
+        self.kick *= (-self.kick_recovery * dt).exp();
     }
 
     fn translation(&self) -> Vec3 {
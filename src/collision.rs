@@ -0,0 +1,597 @@
+use crate::{
+    spatial::{rebuild_spatial_hash, SpatialHash},
+    state::{AppState, InGame},
+};
+use bevy::{prelude::*, utils::HashSet};
+use serde::Deserialize;
+use std::{f32::consts::TAU, ops::BitOr};
+
+pub struct CollisionPlugin;
+
+impl Plugin for CollisionPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<CollisionStarted>()
+            .init_resource::<Contacts>()
+            .add_systems(OnExit(InGame), clear_contacts)
+            .add_systems(
+                FixedPostUpdate,
+                detect_collisions
+                    .after(rebuild_spatial_hash)
+                    .run_if(in_state(AppState::Playing)),
+            );
+    }
+}
+
+/// Makes an entity take part in collision detection.
+///
+/// Two colliders only interact when each one's `mask` contains the other's `layers`.
+#[derive(Component, Debug, Clone)]
+pub struct Collider {
+    pub shape: ColliderShape,
+    pub layers: Layers,
+    pub mask: Layers,
+}
+
+impl Collider {
+    pub fn new(shape: ColliderShape, layers: Layers, mask: Layers) -> Self {
+        Self {
+            shape,
+            layers,
+            mask,
+        }
+    }
+
+    fn interacts_with(&self, other: &Collider) -> bool {
+        self.mask.intersects(other.layers) && other.mask.intersects(self.layers)
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub enum ColliderShape {
+    Circle {
+        radius: f32,
+    },
+    /// A segment of length `2 * half_length` along the local x axis, inflated by `radius`.
+    Capsule {
+        half_length: f32,
+        radius: f32,
+    },
+    /// Vertices of a convex polygon, in either winding order.
+    Polygon {
+        vertices: Vec<Vec2>,
+    },
+}
+
+impl ColliderShape {
+    /// Matches the outline of `build_mesh(radius, vertices)`.
+    pub fn regular_polygon(radius: f32, vertices: usize) -> Self {
+        Self::Polygon {
+            vertices: (0..vertices)
+                .map(|i| Vec2::from_angle(i as f32 / vertices as f32 * TAU) * radius)
+                .collect(),
+        }
+    }
+
+    /// Radius of a circle centered on the entity that contains the whole shape.
+    pub fn bounding_radius(&self) -> f32 {
+        match self {
+            Self::Circle { radius } => *radius,
+            Self::Capsule {
+                half_length,
+                radius,
+            } => half_length + radius,
+            Self::Polygon { vertices } => vertices
+                .iter()
+                .map(|vertex| vertex.length())
+                .fold(0., f32::max),
+        }
+    }
+
+    /// Writes the convex core of the shape in world space, returning the radius it is inflated by.
+    fn core(&self, transform: &Transform, out: &mut Vec<Vec2>) -> f32 {
+        let to_world = |vertex: Vec2| transform.transform_point(vertex.extend(0.)).truncate();
+
+        out.clear();
+        match self {
+            Self::Circle { radius } => {
+                out.push(transform.translation.truncate());
+                *radius
+            }
+            Self::Capsule {
+                half_length,
+                radius,
+            } => {
+                out.push(to_world(Vec2::new(-half_length, 0.)));
+                out.push(to_world(Vec2::new(*half_length, 0.)));
+                *radius
+            }
+            Self::Polygon { vertices } => {
+                out.extend(vertices.iter().copied().map(to_world));
+                0.
+            }
+        }
+    }
+}
+
+/// Bit set of collision layers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Layers(pub u32);
+
+impl Layers {
+    pub const PLAYER: Self = Self(1 << 0);
+    pub const ENEMY: Self = Self(1 << 1);
+    pub const PLAYER_BULLET: Self = Self(1 << 2);
+    pub const ENEMY_BULLET: Self = Self(1 << 3);
+    pub const NEUTRAL_BULLET: Self = Self(1 << 4);
+    pub const PICKUP: Self = Self(1 << 5);
+    pub const WALL: Self = Self(1 << 6);
+
+    pub fn intersects(self, other: Layers) -> bool {
+        self.0 & other.0 != 0
+    }
+}
+
+impl BitOr for Layers {
+    type Output = Self;
+
+    fn bitor(self, rhs: Self) -> Self {
+        Self(self.0 | rhs.0)
+    }
+}
+
+/// Sent on the first fixed step two colliders overlap.
+#[derive(Event, Debug, Clone, Copy)]
+pub struct CollisionStarted(pub Entity, pub Entity);
+
+impl CollisionStarted {
+    /// Both orderings of the pair, so that systems can match on `(a, b)` regardless of which came first.
+    pub fn pairs(&self) -> [(Entity, Entity); 2] {
+        [(self.0, self.1), (self.1, self.0)]
+    }
+}
+
+/// Pairs that overlapped on the last fixed step, ordered so that the smaller entity comes first.
+///
+/// Unlike `CollisionStarted`, lets systems react to overlaps that began while they ignored them.
+#[derive(Resource, Default)]
+pub struct Contacts {
+    current: HashSet<(Entity, Entity)>,
+    previous: HashSet<(Entity, Entity)>,
+}
+
+impl Contacts {
+    /// Both orderings of every overlapping pair, so that systems can match on `(a, b)`.
+    pub fn pairs(&self) -> impl Iterator<Item = (Entity, Entity)> + '_ {
+        self.current.iter().flat_map(|&(a, b)| [(a, b), (b, a)])
+    }
+}
+
+fn detect_collisions(
+    colliders: Query<(Entity, &Transform, &Collider)>,
+    hash: Res<SpatialHash>,
+    mut contacts: ResMut<Contacts>,
+    mut started: EventWriter<CollisionStarted>,
+) {
+    let contacts = &mut *contacts;
+    std::mem::swap(&mut contacts.current, &mut contacts.previous);
+    contacts.current.clear();
+
+    let mut core_a = Vec::new();
+    let mut core_b = Vec::new();
+
+    for (a, transform_a, collider_a) in colliders.iter() {
+        let center = transform_a.translation.truncate();
+        let radius_a = collider_a.shape.core(transform_a, &mut core_a);
+
+        for b in hash.query(center, collider_a.shape.bounding_radius()) {
+            if b <= a {
+                continue;
+            }
+
+            let Ok((_, transform_b, collider_b)) = colliders.get(b) else {
+                continue;
+            };
+
+            if !collider_a.interacts_with(collider_b) {
+                continue;
+            }
+
+            let radius_b = collider_b.shape.core(transform_b, &mut core_b);
+            if core_distance(&core_a, &core_b) <= radius_a + radius_b {
+                contacts.current.insert((a, b));
+            }
+        }
+    }
+
+    for (a, b) in contacts.current.difference(&contacts.previous) {
+        started.send(CollisionStarted(*a, *b));
+    }
+}
+
+/// Forgets the contacts of the last run, so that overlaps in the next one start over.
+fn clear_contacts(mut contacts: ResMut<Contacts>) {
+    contacts.current.clear();
+    contacts.previous.clear();
+}
+
+/// Distance between two convex cores made of one point, one segment, or a polygon.
+fn core_distance(a: &[Vec2], b: &[Vec2]) -> f32 {
+    if a.is_empty() || b.is_empty() {
+        return f32::MAX;
+    }
+
+    if (a.len() > 2 && polygon_contains(a, b[0])) || (b.len() > 2 && polygon_contains(b, a[0])) {
+        return 0.;
+    }
+
+    let mut min = f32::MAX;
+    for (a0, a1) in edges(a) {
+        for (b0, b1) in edges(b) {
+            min = min.min(segment_distance(a0, a1, b0, b1));
+        }
+    }
+
+    min
+}
+
+/// A point is a single degenerate edge, a segment a single edge.
+fn edges(points: &[Vec2]) -> impl Iterator<Item = (Vec2, Vec2)> + '_ {
+    let count = if points.len() > 2 { points.len() } else { 1 };
+    (0..count).map(move |i| (points[i], points[(i + 1) % points.len()]))
+}
+
+fn polygon_contains(polygon: &[Vec2], point: Vec2) -> bool {
+    let mut sign = 0.;
+    for (a, b) in edges(polygon) {
+        let cross = (b - a).perp_dot(point - a);
+        if cross * sign < 0. {
+            return false;
+        }
+        if cross != 0. {
+            sign = cross;
+        }
+    }
+
+    true
+}
+
+fn segment_distance(a0: Vec2, a1: Vec2, b0: Vec2, b1: Vec2) -> f32 {
+    let side = |p: Vec2, q0: Vec2, q1: Vec2| (q1 - q0).perp_dot(p - q0);
+    if side(a0, b0, b1) * side(a1, b0, b1) < 0. && side(b0, a0, a1) * side(b1, a0, a1) < 0. {
+        return 0.;
+    }
+
+    point_segment_distance(a0, b0, b1)
+        .min(point_segment_distance(a1, b0, b1))
+        .min(point_segment_distance(b0, a0, a1))
+        .min(point_segment_distance(b1, a0, a1))
+}
+
+fn point_segment_distance(point: Vec2, a: Vec2, b: Vec2) -> f32 {
+    let ab = b - a;
+    let length_squared = ab.length_squared();
+    let t = if length_squared > 0. {
+        ((point - a).dot(ab) / length_squared).clamp(0., 1.)
+    } else {
+        0.
+    };
+
+    point.distance(a + ab * t)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::f32::consts::FRAC_PI_2;
+
+    fn overlaps(
+        a: &ColliderShape,
+        transform_a: Transform,
+        b: &ColliderShape,
+        transform_b: Transform,
+    ) -> bool {
+        let (mut core_a, mut core_b) = (Vec::new(), Vec::new());
+        let radius_a = a.core(&transform_a, &mut core_a);
+        let radius_b = b.core(&transform_b, &mut core_b);
+        core_distance(&core_a, &core_b) <= radius_a + radius_b
+    }
+
+    fn at(x: f32, y: f32) -> Transform {
+        Transform::from_xyz(x, y, 0.)
+    }
+
+    fn circle(radius: f32) -> ColliderShape {
+        ColliderShape::Circle { radius }
+    }
+
+    fn capsule(half_length: f32, radius: f32) -> ColliderShape {
+        ColliderShape::Capsule {
+            half_length,
+            radius,
+        }
+    }
+
+    fn square(half_size: f32) -> ColliderShape {
+        ColliderShape::Polygon {
+            vertices: vec![
+                Vec2::new(-half_size, -half_size),
+                Vec2::new(half_size, -half_size),
+                Vec2::new(half_size, half_size),
+                Vec2::new(-half_size, half_size),
+            ],
+        }
+    }
+
+    fn check(
+        cases: &[(
+            &str,
+            ColliderShape,
+            Transform,
+            ColliderShape,
+            Transform,
+            bool,
+        )],
+    ) {
+        for (name, a, transform_a, b, transform_b, expected) in cases {
+            assert_eq!(
+                overlaps(a, *transform_a, b, *transform_b),
+                *expected,
+                "{name}"
+            );
+            assert_eq!(
+                overlaps(b, *transform_b, a, *transform_a),
+                *expected,
+                "{name}, swapped"
+            );
+        }
+    }
+
+    #[test]
+    fn circle_circle() {
+        check(&[
+            (
+                "overlapping",
+                circle(10.),
+                at(0., 0.),
+                circle(10.),
+                at(19., 0.),
+                true,
+            ),
+            (
+                "touching",
+                circle(10.),
+                at(0., 0.),
+                circle(10.),
+                at(20., 0.),
+                true,
+            ),
+            (
+                "apart",
+                circle(10.),
+                at(0., 0.),
+                circle(10.),
+                at(21., 0.),
+                false,
+            ),
+            (
+                "concentric",
+                circle(10.),
+                at(5., 5.),
+                circle(1.),
+                at(5., 5.),
+                true,
+            ),
+        ]);
+    }
+
+    #[test]
+    fn circle_capsule() {
+        let upright = at(0., 0.).with_rotation(Quat::from_rotation_z(FRAC_PI_2));
+        check(&[
+            (
+                "side",
+                capsule(20., 5.),
+                at(0., 0.),
+                circle(5.),
+                at(0., 9.),
+                true,
+            ),
+            (
+                "beside side",
+                capsule(20., 5.),
+                at(0., 0.),
+                circle(5.),
+                at(0., 11.),
+                false,
+            ),
+            (
+                "end cap",
+                capsule(20., 5.),
+                at(0., 0.),
+                circle(5.),
+                at(29., 0.),
+                true,
+            ),
+            (
+                "beyond end cap",
+                capsule(20., 5.),
+                at(0., 0.),
+                circle(5.),
+                at(31., 0.),
+                false,
+            ),
+            (
+                "rotated end cap",
+                capsule(20., 5.),
+                upright,
+                circle(5.),
+                at(0., 29.),
+                true,
+            ),
+            (
+                "rotated away",
+                capsule(20., 5.),
+                upright,
+                circle(5.),
+                at(29., 0.),
+                false,
+            ),
+        ]);
+    }
+
+    #[test]
+    fn capsule_capsule() {
+        let upright = at(0., 0.).with_rotation(Quat::from_rotation_z(FRAC_PI_2));
+        check(&[
+            (
+                "crossing",
+                capsule(20., 5.),
+                at(0., 0.),
+                capsule(20., 5.),
+                upright,
+                true,
+            ),
+            (
+                "parallel",
+                capsule(20., 5.),
+                at(0., 0.),
+                capsule(20., 5.),
+                at(10., 9.),
+                true,
+            ),
+            (
+                "parallel apart",
+                capsule(20., 5.),
+                at(0., 0.),
+                capsule(20., 5.),
+                at(10., 11.),
+                false,
+            ),
+            (
+                "end to end",
+                capsule(20., 5.),
+                at(0., 0.),
+                capsule(20., 5.),
+                at(49., 0.),
+                true,
+            ),
+            (
+                "end to end apart",
+                capsule(20., 5.),
+                at(0., 0.),
+                capsule(20., 5.),
+                at(51., 0.),
+                false,
+            ),
+            (
+                "tee",
+                capsule(20., 5.),
+                at(0., 0.),
+                capsule(20., 5.),
+                upright.with_translation(Vec3::new(0., 29., 0.)),
+                true,
+            ),
+        ]);
+    }
+
+    #[test]
+    fn circle_polygon() {
+        let reversed = ColliderShape::Polygon {
+            vertices: vec![
+                Vec2::new(-10., 10.),
+                Vec2::new(10., 10.),
+                Vec2::new(10., -10.),
+                Vec2::new(-10., -10.),
+            ],
+        };
+        check(&[
+            (
+                "inside",
+                square(10.),
+                at(0., 0.),
+                circle(1.),
+                at(3., -2.),
+                true,
+            ),
+            (
+                "inside, reversed winding",
+                reversed,
+                at(0., 0.),
+                circle(1.),
+                at(3., -2.),
+                true,
+            ),
+            (
+                "edge",
+                square(10.),
+                at(0., 0.),
+                circle(2.),
+                at(11., 0.),
+                true,
+            ),
+            (
+                "beside edge",
+                square(10.),
+                at(0., 0.),
+                circle(2.),
+                at(13., 0.),
+                false,
+            ),
+            (
+                "vertex",
+                square(10.),
+                at(0., 0.),
+                circle(2.),
+                at(11., 11.),
+                true,
+            ),
+            (
+                "beside vertex",
+                square(10.),
+                at(0., 0.),
+                circle(2.),
+                at(12., 12.),
+                false,
+            ),
+            (
+                "outside",
+                square(10.),
+                at(0., 0.),
+                circle(2.),
+                at(50., -30.),
+                false,
+            ),
+            (
+                "moved",
+                square(10.),
+                at(100., 0.),
+                circle(1.),
+                at(100., 0.),
+                true,
+            ),
+        ]);
+    }
+
+    #[test]
+    fn layer_filtering() {
+        let collider = |layers, mask| Collider::new(circle(1.), layers, mask);
+        let player = collider(Layers::PLAYER, Layers::ENEMY | Layers::ENEMY_BULLET);
+        let enemy_bullet = collider(Layers::ENEMY_BULLET, Layers::PLAYER);
+        let player_bullet = collider(Layers::PLAYER_BULLET, Layers::ENEMY);
+        let enemy = collider(Layers::ENEMY, Layers::PLAYER | Layers::PLAYER_BULLET);
+        let deaf = collider(Layers::ENEMY, Layers(0));
+
+        let cases = [
+            ("player, enemy bullet", &player, &enemy_bullet, true),
+            ("player, player bullet", &player, &player_bullet, false),
+            ("enemy, player bullet", &enemy, &player_bullet, true),
+            ("enemy, enemy bullet", &enemy, &enemy_bullet, false),
+            ("player, enemy", &player, &enemy, true),
+            ("one-sided mask", &player, &deaf, false),
+        ];
+        for (name, a, b, expected) in cases {
+            assert_eq!(a.interacts_with(b), expected, "{name}");
+            assert_eq!(b.interacts_with(a), expected, "{name}, swapped");
+        }
+    }
+}
@@ -0,0 +1,174 @@
+use bevy::{prelude::*, sprite::Mesh2dHandle};
+
+use crate::{
+    build_mesh,
+    player::{
+        kill_player, respawn_player, Dead, Invulnerable, Lives, Player, PlayerCount, PlayerDied,
+        PlayerRespawned, PLAYER_INVULNERABILITY,
+    },
+    state::{AppState, InGame},
+    GameSet, Health,
+};
+
+pub struct CoopPlugin;
+
+impl Plugin for CoopPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(Startup, init_revive_assets)
+            .add_systems(
+                FixedUpdate,
+                (
+                    spawn_revive_markers.after(kill_player),
+                    revive_players.after(respawn_player),
+                )
+                    .in_set(GameSet::Death),
+            )
+            .add_systems(
+                Update,
+                update_revive_markers.run_if(in_state(AppState::Playing)),
+            );
+    }
+}
+
+/// Marks where a player out of lives went down, filling up as they are revived.
+#[derive(Component)]
+struct ReviveMarker(Entity);
+
+#[derive(Component)]
+struct ReviveFill;
+
+#[derive(Resource)]
+struct ReviveAssets {
+    mesh: Mesh2dHandle,
+    area: Handle<ColorMaterial>,
+    fill: Handle<ColorMaterial>,
+}
+
+/// A player out of lives is revived by another player staying this close for `REVIVE_DURATION`.
+const REVIVE_RADIUS: f32 = 150.;
+/// Seconds.
+const REVIVE_DURATION: f32 = 3.;
+/// Fraction of their health a revived player comes back with.
+const REVIVE_HEALTH: f32 = 0.5;
+
+fn init_revive_assets(
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+) {
+    commands.insert_resource(ReviveAssets {
+        mesh: meshes.add(build_mesh(REVIVE_RADIUS, 32)).into(),
+        area: materials.add(Color::WHITE.with_alpha(0.1)),
+        fill: materials.add(Color::srgba(0.4, 1., 0.5, 0.4)),
+    });
+}
+
+fn revive_players(
+    mut commands: Commands,
+    mut downed: Query<
+        (
+            Entity,
+            &mut Dead,
+            &mut Lives,
+            &mut Health,
+            &Transform,
+            &mut Visibility,
+        ),
+        With<Player>,
+    >,
+    rescuers: Query<&Transform, (With<Player>, Without<Dead>)>,
+    time: Res<Time>,
+    mut writer: EventWriter<PlayerRespawned>,
+) {
+    for (entity, mut dead, mut lives, mut health, transform, mut visibility) in downed.iter_mut() {
+        if lives.0 > 0 || !dead.finished() {
+            continue;
+        }
+
+        let position = transform.translation;
+        let rescued = rescuers.iter().any(|rescuer| {
+            rescuer.translation.distance_squared(position) < REVIVE_RADIUS * REVIVE_RADIUS
+        });
+
+        // Progress is lost as soon as the rescuer steps away.
+        dead.revive = if rescued {
+            dead.revive + time.delta_seconds()
+        } else {
+            0.
+        };
+        if dead.revive < REVIVE_DURATION {
+            continue;
+        }
+
+        lives.0 = 1;
+        health.current = health.max * REVIVE_HEALTH;
+        *visibility = Visibility::Inherited;
+        commands
+            .entity(entity)
+            .remove::<Dead>()
+            .insert(Invulnerable::from_seconds(PLAYER_INVULNERABILITY));
+
+        writer.send(PlayerRespawned {
+            player: entity,
+            position,
+        });
+    }
+}
+
+/// Alone, a player out of lives can't be revived, and the game over screen takes over instead.
+fn spawn_revive_markers(
+    mut commands: Commands,
+    mut reader: EventReader<PlayerDied>,
+    count: Res<PlayerCount>,
+    assets: Res<ReviveAssets>,
+) {
+    for died in reader.read() {
+        if died.lives > 0 || count.0 < 2 {
+            continue;
+        }
+
+        commands
+            .spawn((
+                ReviveMarker(died.player),
+                StateScoped(InGame),
+                ColorMesh2dBundle {
+                    mesh: assets.mesh.clone(),
+                    material: assets.area.clone(),
+                    transform: Transform::from_translation(died.position.with_z(-1.)),
+                    ..Default::default()
+                },
+            ))
+            .with_children(|parent| {
+                parent.spawn((
+                    ReviveFill,
+                    ColorMesh2dBundle {
+                        mesh: assets.mesh.clone(),
+                        material: assets.fill.clone(),
+                        transform: Transform::from_xyz(0., 0., 0.1).with_scale(Vec3::ZERO),
+                        ..Default::default()
+                    },
+                ));
+            });
+    }
+}
+
+fn update_revive_markers(
+    mut commands: Commands,
+    markers: Query<(Entity, &ReviveMarker, &Children)>,
+    players: Query<&Dead, With<Player>>,
+    mut fills: Query<&mut Transform, With<ReviveFill>>,
+) {
+    for (entity, marker, children) in markers.iter() {
+        // Revived.
+        let Ok(dead) = players.get(marker.0) else {
+            commands.entity(entity).despawn_recursive();
+            continue;
+        };
+
+        let progress = (dead.revive / REVIVE_DURATION).min(1.);
+        let mut fills = fills.iter_many_mut(children);
+        while let Some(mut transform) = fills.fetch_next() {
+            transform.scale = Vec3::splat(progress);
+        }
+    }
+}
@@ -0,0 +1,194 @@
+use bevy::{prelude::*, sprite::Mesh2dHandle};
+
+use crate::{
+    player::{move_player, spawn_players, Aim, BufferedInput, Dead, Player, PlayerHud, HUD_ROW},
+    state::{AppState, InGame},
+    GameSet, Velocity,
+};
+
+pub struct DashPlugin;
+
+impl Plugin for DashPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(Startup, init_afterimage_material)
+            .add_systems(OnEnter(InGame), spawn_dash_text.after(spawn_players))
+            .add_systems(
+                FixedUpdate,
+                (update_dash.before(move_player), spawn_afterimages)
+                    .chain()
+                    .in_set(GameSet::Control),
+            )
+            .add_systems(
+                Update,
+                (update_dash_text, fade_afterimages).run_if(in_state(AppState::Playing)),
+            );
+    }
+}
+
+/// Bursts the player forward, faster than `PLAYER_MAX_SPEED`, and bullets pass through them while it
+/// lasts.
+#[derive(Component, Debug, Default, Clone, Copy)]
+pub struct Dash {
+    direction: Vec2,
+    /// Seconds left in the current dash.
+    time_left: f32,
+    /// Seconds until the next dash, counted from its start.
+    cooldown: f32,
+}
+
+impl Dash {
+    pub fn active(&self) -> bool {
+        self.time_left > 0.
+    }
+
+    pub fn ready(&self) -> bool {
+        self.cooldown <= 0.
+    }
+}
+
+#[derive(Component)]
+struct DashText;
+
+/// Left behind by a dashing player, shrinking until it disappears.
+#[derive(Component)]
+struct Afterimage {
+    timer: Timer,
+}
+
+#[derive(Resource)]
+struct AfterimageMaterial(Handle<ColorMaterial>);
+
+const DASH_SPEED: f32 = 2500.;
+/// Seconds.
+const DASH_DURATION: f32 = 0.15;
+const DASH_COOLDOWN: f32 = 1.;
+/// Seconds an afterimage stays visible.
+const AFTERIMAGE_LIFETIME: f32 = 0.25;
+
+/// Runs before `move_player`, which leaves dashing players alone.
+fn update_dash(
+    mut player: Query<
+        (
+            &Transform,
+            &mut Velocity,
+            &mut BufferedInput,
+            &mut Dash,
+            &Aim,
+        ),
+        (With<Player>, Without<Dead>),
+    >,
+    time: Res<Time>,
+) {
+    let dt = time.delta_seconds();
+    for (transform, mut velocity, mut input, mut dash, aim) in player.iter_mut() {
+        dash.time_left = (dash.time_left - dt).max(0.);
+        dash.cooldown = (dash.cooldown - dt).max(0.);
+
+        if std::mem::take(&mut input.dash) && dash.ready() {
+            // Standing still, the player dashes towards where they aim.
+            let direction = input.movement.try_normalize().or_else(|| {
+                aim.0
+                    .and_then(|aim| (aim - transform.translation.truncate()).try_normalize())
+            });
+
+            if let Some(direction) = direction {
+                *dash = Dash {
+                    direction,
+                    time_left: DASH_DURATION,
+                    cooldown: DASH_COOLDOWN,
+                };
+            }
+        }
+
+        if dash.active() {
+            velocity.add_velocity_clamped(dash.direction.extend(0.) * DASH_SPEED, DASH_SPEED);
+        }
+    }
+}
+
+fn init_afterimage_material(mut commands: Commands, mut materials: ResMut<Assets<ColorMaterial>>) {
+    commands.insert_resource(AfterimageMaterial(
+        materials.add(Color::WHITE.with_alpha(0.3)),
+    ));
+}
+
+fn spawn_afterimages(
+    mut commands: Commands,
+    player: Query<(&Transform, &Dash, &Mesh2dHandle), With<Player>>,
+    material: Res<AfterimageMaterial>,
+) {
+    for (transform, dash, mesh) in player.iter() {
+        if !dash.active() {
+            continue;
+        }
+
+        commands.spawn((
+            Afterimage {
+                timer: Timer::from_seconds(AFTERIMAGE_LIFETIME, TimerMode::Once),
+            },
+            StateScoped(InGame),
+            ColorMesh2dBundle {
+                mesh: mesh.clone(),
+                material: material.0.clone(),
+                transform: transform.with_translation(transform.translation.with_z(-0.5)),
+                ..Default::default()
+            },
+        ));
+    }
+}
+
+fn fade_afterimages(
+    mut commands: Commands,
+    mut afterimages: Query<(Entity, &mut Afterimage, &mut Transform)>,
+    time: Res<Time>,
+) {
+    for (entity, mut afterimage, mut transform) in afterimages.iter_mut() {
+        if afterimage.timer.tick(time.delta()).finished() {
+            commands.entity(entity).despawn();
+            continue;
+        }
+
+        transform.scale = Vec3::splat(afterimage.timer.fraction_remaining());
+    }
+}
+
+fn spawn_dash_text(mut commands: Commands, players: Query<(Entity, &Player)>) {
+    for (entity, player) in players.iter() {
+        commands.spawn((
+            DashText,
+            PlayerHud(entity),
+            StateScoped(InGame),
+            TextBundle::from_section(
+                "",
+                TextStyle {
+                    font_size: 32.,
+                    color: player.color(),
+                    ..Default::default()
+                },
+            )
+            .with_style(Style {
+                position_type: PositionType::Absolute,
+                right: Val::Px(20.),
+                top: Val::Px(20. + HUD_ROW * (2 * player.0 + 1) as f32),
+                ..Default::default()
+            }),
+        ));
+    }
+}
+
+fn update_dash_text(
+    players: Query<&Dash, Changed<Dash>>,
+    mut texts: Query<(&mut Text, &PlayerHud), With<DashText>>,
+) {
+    for (mut text, hud) in texts.iter_mut() {
+        let Ok(dash) = players.get(hud.0) else {
+            continue;
+        };
+
+        text.sections[0].value = if dash.ready() {
+            "Dash: ready".to_string()
+        } else {
+            format!("Dash: {:.1}s", dash.cooldown)
+        };
+    }
+}
@@ -0,0 +1,250 @@
+use crate::{
+    bullet::{BulletType, Faction, SpawnBullet},
+    player::{nearest_player, Dead, Player},
+    GameSet,
+};
+use bevy::prelude::*;
+use std::{f32::consts::TAU, time::Duration};
+
+pub struct EmitterPlugin;
+
+impl Plugin for EmitterPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(FixedUpdate, update_emitters.in_set(GameSet::Control));
+    }
+}
+
+/// Fires a volley of `SpawnBullet` events shaped by `pattern` every time its timer finishes.
+///
+/// Attach it to anything with a `Transform`; aimed patterns target the nearest `Player`.
+#[derive(Component)]
+pub struct BulletEmitter {
+    pub ty: BulletType,
+    pub faction: Faction,
+    pub pattern: Pattern,
+    /// Distance from the emitter's center at which bullets are spawned.
+    pub offset: f32,
+    timer: Timer,
+    burst: Option<Burst>,
+    elapsed: f32,
+}
+
+struct Burst {
+    volleys: u32,
+    fired: u32,
+    cooldown: Timer,
+}
+
+impl BulletEmitter {
+    pub fn from_seconds(seconds: f32, ty: BulletType, pattern: Pattern) -> Self {
+        Self {
+            ty,
+            faction: Faction::Enemy,
+            pattern,
+            offset: 0.,
+            timer: Timer::from_seconds(seconds, TimerMode::Repeating),
+            burst: None,
+            elapsed: 0.,
+        }
+    }
+
+    pub fn with_offset(mut self, offset: f32) -> Self {
+        self.offset = offset;
+        self
+    }
+
+    /// Scales how often the emitter fires, e.g. a `rate` of 2 fires twice as often.
+    pub fn with_rate(mut self, rate: f32) -> Self {
+        let duration = self.timer.duration().div_f32(rate);
+        self.timer.set_duration(duration);
+        self
+    }
+
+    /// Fire `volleys` volleys back to back, then wait `cooldown` seconds before the next burst.
+    pub fn with_burst(mut self, volleys: u32, cooldown: f32) -> Self {
+        self.burst = Some(Burst {
+            volleys,
+            fired: 0,
+            cooldown: Timer::from_seconds(cooldown, TimerMode::Once),
+        });
+        self
+    }
+
+    /// Returns the number of volleys to fire this frame.
+    fn tick(&mut self, delta: Duration) -> u32 {
+        self.elapsed += delta.as_secs_f32();
+
+        if let Some(burst) = &mut self.burst {
+            if burst.fired >= burst.volleys {
+                if !burst.cooldown.tick(delta).finished() {
+                    return 0;
+                }
+
+                burst.fired = 0;
+                self.timer.reset();
+            }
+        }
+
+        let mut volleys = self.timer.tick(delta).times_finished_this_tick();
+
+        if let Some(burst) = &mut self.burst {
+            volleys = volleys.min(burst.volleys - burst.fired);
+            burst.fired += volleys;
+            if burst.fired >= burst.volleys {
+                burst.cooldown.reset();
+            }
+        }
+
+        volleys
+    }
+}
+
+/// Describes the directions of a single volley.
+///
+/// Patterns compose: `Pattern::ring(4).spiral(2.).and(Pattern::aimed(3, 0.4))`.
+#[derive(Clone, Debug)]
+pub enum Pattern {
+    /// `count` bullets evenly spaced around a full circle.
+    Ring { count: usize },
+    /// `count` bullets fanned across `arc` radians, centered on the player.
+    Aimed { count: usize, arc: f32 },
+    /// Rotates `pattern` by `angular_velocity` radians per second over the emitter's lifetime.
+    Spiral {
+        pattern: Box<Pattern>,
+        angular_velocity: f32,
+    },
+    /// Rotates `pattern` by a fixed `angle` in radians.
+    Rotated { pattern: Box<Pattern>, angle: f32 },
+    /// Fires every pattern at once.
+    Compose(Vec<Pattern>),
+}
+
+impl Pattern {
+    pub fn ring(count: usize) -> Self {
+        Self::Ring { count }
+    }
+
+    pub fn aimed(count: usize, arc: f32) -> Self {
+        Self::Aimed { count, arc }
+    }
+
+    pub fn spiral(self, angular_velocity: f32) -> Self {
+        Self::Spiral {
+            pattern: Box::new(self),
+            angular_velocity,
+        }
+    }
+
+    pub fn rotated(self, angle: f32) -> Self {
+        Self::Rotated {
+            pattern: Box::new(self),
+            angle,
+        }
+    }
+
+    pub fn and(self, other: Pattern) -> Self {
+        match self {
+            Self::Compose(mut patterns) => {
+                patterns.push(other);
+                Self::Compose(patterns)
+            }
+            pattern => Self::Compose(vec![pattern, other]),
+        }
+    }
+
+    /// Pushes the angle, in radians, of every bullet in the volley.
+    ///
+    /// `aim` is the angle towards the player, `elapsed` the lifetime of the emitter in seconds.
+    fn angles(&self, aim: f32, elapsed: f32, rotation: f32, out: &mut Vec<f32>) {
+        match self {
+            Self::Ring { count } => {
+                let step = TAU / *count as f32;
+                out.extend((0..*count).map(|i| rotation + i as f32 * step));
+            }
+            Self::Aimed { count, arc } => match *count {
+                0 => {}
+                1 => out.push(aim + rotation),
+                count => {
+                    let step = arc / (count - 1) as f32;
+                    let start = aim + rotation - arc / 2.;
+                    out.extend((0..count).map(|i| start + i as f32 * step));
+                }
+            },
+            Self::Spiral {
+                pattern,
+                angular_velocity,
+            } => pattern.angles(aim, elapsed, rotation + angular_velocity * elapsed, out),
+            Self::Rotated { pattern, angle } => pattern.angles(aim, elapsed, rotation + angle, out),
+            Self::Compose(patterns) => {
+                for pattern in patterns.iter() {
+                    pattern.angles(aim, elapsed, rotation, out);
+                }
+            }
+        }
+    }
+}
+
+fn update_emitters(
+    mut emitters: Query<(&Transform, &mut BulletEmitter)>,
+    players: Query<(&Transform, Has<Dead>), With<Player>>,
+    time: Res<Time>,
+    mut writer: EventWriter<SpawnBullet>,
+) {
+    let mut angles = Vec::new();
+
+    for (transform, mut emitter) in emitters.iter_mut() {
+        let volleys = emitter.tick(time.delta());
+        if volleys == 0 {
+            continue;
+        }
+
+        let position = transform.translation;
+        let aim = nearest_player(&players, position)
+            .and_then(|t| (t - position).truncate().try_normalize())
+            .unwrap_or(Vec2::NEG_Y)
+            .to_angle();
+
+        for _ in 0..volleys {
+            angles.clear();
+            emitter
+                .pattern
+                .angles(aim, emitter.elapsed, 0., &mut angles);
+
+            for angle in angles.iter() {
+                let direction = Vec2::from_angle(*angle).extend(0.);
+                writer.send(SpawnBullet {
+                    ty: emitter.ty.clone(),
+                    faction: emitter.faction,
+                    owner: None,
+                    position: position + direction * emitter.offset,
+                    direction,
+                });
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn angles(pattern: &Pattern, aim: f32) -> Vec<f32> {
+        let mut out = Vec::new();
+        pattern.angles(aim, 0., 0., &mut out);
+        out
+    }
+
+    #[test]
+    fn aimed_counts() {
+        assert_eq!(angles(&Pattern::aimed(0, 1.), 0.5), Vec::<f32>::new());
+        assert_eq!(angles(&Pattern::aimed(1, 1.), 0.5), vec![0.5]);
+        assert_eq!(angles(&Pattern::aimed(2, 1.), 0.5), vec![0., 1.]);
+    }
+
+    #[test]
+    fn ring_counts() {
+        assert_eq!(angles(&Pattern::ring(0), 0.), Vec::<f32>::new());
+        assert_eq!(angles(&Pattern::ring(1), 0.), vec![0.]);
+        assert_eq!(angles(&Pattern::ring(2), 0.), vec![0., TAU / 2.]);
+    }
+}
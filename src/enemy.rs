@@ -1,61 +1,133 @@
-use std::{sync::Arc, time::Duration};
-
-use crate::{add_health_bar, build_mesh, bullet::Bullet, Health, Velocity};
-use bevy::{
-    prelude::*,
-    sprite::{Material2d, Mesh2dHandle},
-    utils::HashMap,
+use crate::{
+    add_health_bar, build_mesh,
+    bullet::{BulletType, Faction},
+    collision::{Collider, ColliderShape, Layers},
+    emitter::{BulletEmitter, Pattern},
+    interpolation::Interpolated,
+    pickup::{DropTable, PickupKind},
+    state::InGame,
+    steering::{Chase, Flee, FollowPath, Orbit, Steering, SteeringPlugin, Strafe, Wander},
+    wave::WavePlugin,
+    Friction, Health, Velocity,
 };
+use bevy::{ecs::system::EntityCommands, prelude::*};
 use rand::Rng;
+use std::f32::consts::PI;
 
 pub struct EnemyPlugin;
 
 impl Plugin for EnemyPlugin {
     fn build(&self, app: &mut App) {
-        app.add_systems(Startup, spawn_enemy);
+        app.add_plugins((WavePlugin, SteeringPlugin))
+            .add_event::<EnemyKilled>();
     }
 }
 
 #[derive(Component)]
 pub struct Enemy;
 
+/// Sent when a bullet or a special takes an enemy's last health.
+#[derive(Event, Debug, Clone, Copy)]
+pub struct EnemyKilled {
+    pub enemy: Entity,
+    pub position: Vec3,
+    /// The player credited with the kill, if any.
+    pub by: Option<Entity>,
+}
+
 pub const ENEMY_RADIUS: f32 = 40.;
+const ENEMY_ACCELERATION: f32 = 2000.;
+const ENEMY_MAX_SPEED: f32 = 300.;
+const ENEMY_FRICTION: f32 = 1000.;
 
-pub fn spawn_enemy(mut commands: Commands, mut meshes: ResMut<Assets<Mesh>>) {
+/// Health and fire rate are scaled by `difficulty`.
+pub fn spawn_enemy(
+    commands: &mut Commands,
+    meshes: &mut Assets<Mesh>,
+    asset_server: &AssetServer,
+    rng: &mut impl Rng,
+    position: Vec3,
+    difficulty: f32,
+) {
     let mesh = build_mesh(ENEMY_RADIUS, 4);
+    let transform = Transform::from_translation(position);
+
+    let mut enemy = commands.spawn((
+        Enemy,
+        StateScoped(InGame),
+        ColorMesh2dBundle {
+            mesh: meshes.add(mesh).into(),
+            transform,
+            ..Default::default()
+        },
+        Interpolated::new(&transform),
+        Health::from_max(3. * difficulty),
+        Faction::Enemy,
+        Collider::new(
+            ColliderShape::regular_polygon(ENEMY_RADIUS, 4),
+            Layers::ENEMY,
+            Layers::PLAYER | Layers::PLAYER_BULLET | Layers::NEUTRAL_BULLET | Layers::WALL,
+        ),
+        random_emitter(asset_server, rng).with_rate(difficulty),
+        Velocity::default(),
+        Friction(ENEMY_FRICTION),
+        Steering {
+            acceleration: ENEMY_ACCELERATION,
+            max_speed: ENEMY_MAX_SPEED,
+        },
+        DropTable::new(0.2)
+            .with(PickupKind::Health, 4)
+            .with(PickupKind::FireRate, 3)
+            .with(PickupKind::Shield, 2)
+            .with(PickupKind::ScoreMultiplier, 2)
+            .with(PickupKind::ExtraProjectile, 1),
+    ));
+    insert_random_behaviors(&mut enemy, rng, position.truncate());
 
-    let x = rand::thread_rng().gen_range(-960.0 / 2.0..960.0 / 2.0);
-    let y = rand::thread_rng().gen_range(-540.0 / 2.0..540.0 / 2.0);
-
-    let enemy = commands
-        .spawn((
-            Enemy,
-            ColorMesh2dBundle {
-                mesh: meshes.add(mesh).into(),
-                transform: Transform::from_xyz(x, y, 0.),
-                ..Default::default()
-            },
-            Health::from_max(3.),
-            // BulletSpawner::from_seconds(1.0, spawn_in_random_dir),
-        ))
-        .id();
-
-    add_health_bar(&mut commands, enemy, 70.);
+    let enemy = enemy.id();
+    add_health_bar(commands, enemy, 70.);
 }
 
-// fn spawn_in_random_dir(arena: &mut BulletArena, commands: &mut Commands, position: Vec3) {
-//     // let velocity = Vec3::Y * BULLET_SPEED;
-//     // Bullet::spawn(commands, BulletType::Ball, velocity, position);
-// }
+fn insert_random_behaviors(enemy: &mut EntityCommands, rng: &mut impl Rng, position: Vec2) {
+    let wander = Wander::new(0.5, rng.gen_range(0.0..1000.0));
 
-pub fn update_enemy(
-    // mut enemies: Query<(&Transform, &mut BulletSpawner), With<Enemy>>,
-    time: Res<Time>,
-) {
-    // for (position, mut spawner) in enemies.iter_mut() {
-    //     spawner.timer.tick(time.delta());
-    //     if spawner.timer.just_finished() {
-    //         (spawner.f)(position.translation);
-    //     }
-    // }
+    match rng.gen_range(0..6) {
+        0 => enemy.insert(Chase),
+        1 => enemy.insert(Orbit {
+            radius: rng.gen_range(250.0..450.0),
+            clockwise: rng.gen(),
+        }),
+        2 => enemy.insert(Strafe::new(rng.gen_range(300.0..500.0), 2.)),
+        3 => enemy.insert((Flee { radius: 300. }, wander)),
+        4 => {
+            // A loop through the spawn point and the center of the arena.
+            let center = position / 2.;
+            let points = (0..4)
+                .map(|i| center + Vec2::from_angle(i as f32 * PI / 2.).rotate(position - center))
+                .collect();
+            enemy.insert(FollowPath::new(points, 200., true))
+        }
+        _ => enemy.insert(wander),
+    };
+}
+
+fn random_emitter(asset_server: &AssetServer, rng: &mut impl Rng) -> BulletEmitter {
+    let orb = BulletType::named(asset_server, "orb");
+    let needle = BulletType::named(asset_server, "needle");
+    let petal = BulletType::named(asset_server, "petal");
+
+    let emitter = match rng.gen_range(0..4) {
+        0 => BulletEmitter::from_seconds(1.5, orb, Pattern::ring(12)),
+        1 => BulletEmitter::from_seconds(0.15, needle, Pattern::aimed(3, 0.4)).with_burst(3, 1.5),
+        2 => BulletEmitter::from_seconds(0.1, orb, Pattern::ring(4).spiral(2.)),
+        _ => BulletEmitter::from_seconds(
+            0.2,
+            petal,
+            Pattern::ring(6)
+                .spiral(1.)
+                .and(Pattern::ring(6).rotated(PI / 6.).spiral(-1.)),
+        ),
+    };
+
+    emitter.with_offset(ENEMY_RADIUS)
 }
@@ -0,0 +1,66 @@
+use bevy::{prelude::*, sprite::Mesh2dHandle};
+
+use crate::{
+    build_mesh,
+    player::{spawn_players, BufferedInput, Player, PLAYER_HITBOX_RADIUS},
+    state::{AppState, InGame},
+};
+
+pub struct FocusPlugin;
+
+impl Plugin for FocusPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(OnEnter(InGame), spawn_hitboxes.after(spawn_players))
+            .add_systems(Update, show_hitbox.run_if(in_state(AppState::Playing)));
+    }
+}
+
+/// Shows the player's hitbox while focused.
+#[derive(Component)]
+struct Hitbox;
+
+pub const FOCUS_MAX_SPEED: f32 = 400.;
+pub const FOCUS_ACCELERATION: f32 = 38400.;
+/// Scales the spread of the player's weapons while focused.
+pub const FOCUS_SPREAD: f32 = 0.35;
+
+fn spawn_hitboxes(
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+    players: Query<Entity, With<Player>>,
+) {
+    let mesh: Mesh2dHandle = meshes.add(build_mesh(PLAYER_HITBOX_RADIUS, 12)).into();
+    let material = materials.add(Color::srgb(1., 0.2, 0.3));
+
+    for player in players.iter() {
+        commands.entity(player).with_children(|parent| {
+            parent.spawn((
+                Hitbox,
+                ColorMesh2dBundle {
+                    mesh: mesh.clone(),
+                    material: material.clone(),
+                    transform: Transform::from_xyz(0., 0., 1.),
+                    visibility: Visibility::Hidden,
+                    ..Default::default()
+                },
+            ));
+        });
+    }
+}
+
+fn show_hitbox(
+    player: Query<(&BufferedInput, &Children), With<Player>>,
+    mut hitboxes: Query<&mut Visibility, With<Hitbox>>,
+) {
+    for (input, children) in player.iter() {
+        let mut hitboxes = hitboxes.iter_many_mut(children);
+        while let Some(mut visibility) = hitboxes.fetch_next() {
+            *visibility = if input.focus {
+                Visibility::Inherited
+            } else {
+                Visibility::Hidden
+            };
+        }
+    }
+}
@@ -0,0 +1,283 @@
+use crate::{
+    bullet::{Bullet, DespawnBullet, Faction, GrazeState},
+    camera::{PlayerCamera, ScreenShake},
+    enemy::{Enemy, EnemyKilled},
+    pickup::Modifiers,
+    player::{spawn_players, BufferedInput, Dead, Invulnerable, Player, PlayerHud, HUD_ROW},
+    score::Scores,
+    state::{AppState, InGame},
+    GameSet, Health,
+};
+use bevy::prelude::*;
+use leafwing_input_manager::prelude::*;
+
+pub struct GrazePlugin;
+
+impl Plugin for GrazePlugin {
+    fn build(&self, app: &mut App) {
+        app.add_plugins(InputManagerPlugin::<SpecialAction>::default())
+            .add_event::<Grazed>()
+            .add_systems(OnEnter(InGame), spawn_graze_meter.after(spawn_players))
+            .add_systems(
+                FixedUpdate,
+                (
+                    release_special.in_set(GameSet::Control),
+                    (detect_grazes, charge_graze_meters)
+                        .chain()
+                        .in_set(GameSet::Damage),
+                ),
+            )
+            .add_systems(
+                Update,
+                (
+                    (spawn_graze_sparks, update_graze_sparks).chain(),
+                    update_graze_meter,
+                )
+                    .run_if(in_state(AppState::Playing)),
+            );
+    }
+}
+
+/// Spends a full `GrazeMeter` to clear the enemy bullets around the player and hurt nearby enemies.
+#[derive(Debug, Actionlike, PartialEq, Eq, Hash, Clone, Copy, Reflect)]
+pub struct SpecialAction;
+
+/// Sent when an enemy bullet passes close to a player without hitting them.
+#[derive(Event, Debug, Clone, Copy)]
+pub struct Grazed {
+    pub player: Entity,
+    pub position: Vec3,
+}
+
+/// Charged by grazing, and spent all at once on the special.
+#[derive(Component, Debug, Default, Clone, Copy)]
+pub struct GrazeMeter {
+    /// Between 0 and 1.
+    pub charge: f32,
+}
+
+impl GrazeMeter {
+    pub fn full(&self) -> bool {
+        self.charge >= 1.
+    }
+}
+
+/// Enemy bullets passing closer than this to the center of a player graze them.
+pub const GRAZE_RADIUS: f32 = 100.;
+/// Meter charged per graze.
+const GRAZE_CHARGE: f32 = 0.02;
+
+const SPECIAL_RADIUS: f32 = 800.;
+const SPECIAL_DAMAGE: f32 = 10.;
+
+/// A bullet is counted once it leaves the graze radius, so that bullets that end up hitting don't count.
+fn detect_grazes(
+    mut bullets: Query<(&Transform, &mut Bullet)>,
+    players: Query<(Entity, &Transform), (With<Player>, Without<Dead>, Without<Invulnerable>)>,
+    mut writer: EventWriter<Grazed>,
+) {
+    let near = |player: &Transform, bullet: Vec3| {
+        player.translation.distance_squared(bullet) < GRAZE_RADIUS * GRAZE_RADIUS
+    };
+
+    for (transform, mut bullet) in bullets.iter_mut() {
+        if !bullet.faction.can_damage(Faction::Player) {
+            continue;
+        }
+
+        let position = transform.translation;
+        match bullet.graze {
+            GrazeState::Away => {
+                if let Some((player, ..)) =
+                    players.iter().find(|(_, player)| near(player, position))
+                {
+                    bullet.graze = GrazeState::Near(player);
+                }
+            }
+            GrazeState::Near(player) => {
+                // The player died or became invulnerable while the bullet went by.
+                let Ok((_, player_transform)) = players.get(player) else {
+                    bullet.graze = GrazeState::Away;
+                    continue;
+                };
+
+                if near(player_transform, position) {
+                    continue;
+                }
+
+                bullet.graze = GrazeState::Grazed;
+                writer.send(Grazed { player, position });
+            }
+            GrazeState::Grazed => {}
+        }
+    }
+}
+
+/// Charges the meter of the grazed player, and scores the graze.
+fn charge_graze_meters(
+    mut reader: EventReader<Grazed>,
+    mut players: Query<(&Player, &mut GrazeMeter, &Modifiers)>,
+    mut scores: ResMut<Scores>,
+) {
+    for grazed in reader.read() {
+        let Ok((player, mut meter, modifiers)) = players.get_mut(grazed.player) else {
+            continue;
+        };
+
+        meter.charge = (meter.charge + GRAZE_CHARGE).min(1.);
+        if let Some(score) = scores.0.get_mut(player.0) {
+            score.add_graze(modifiers.score_multiplier());
+        }
+    }
+}
+
+fn release_special(
+    mut players: Query<
+        (Entity, &Transform, &mut BufferedInput, &mut GrazeMeter),
+        (With<Player>, Without<Dead>),
+    >,
+    bullets: Query<(Entity, &Transform, &Bullet)>,
+    mut enemies: Query<(Entity, &Transform, &mut Health), With<Enemy>>,
+    mut despawns: EventWriter<DespawnBullet>,
+    mut killed: EventWriter<EnemyKilled>,
+    mut player_camera: ResMut<PlayerCamera>,
+) {
+    for (player, player_transform, mut input, mut meter) in players.iter_mut() {
+        if !std::mem::take(&mut input.special) || !meter.full() {
+            continue;
+        }
+
+        meter.charge = 0.;
+        player_camera.push_screen_shake(ScreenShake::new(0.8));
+
+        let center = player_transform.translation;
+        let in_range = |transform: &Transform| {
+            transform.translation.distance_squared(center) < SPECIAL_RADIUS * SPECIAL_RADIUS
+        };
+
+        for (entity, transform, bullet) in bullets.iter() {
+            if bullet.faction.can_damage(Faction::Player) && in_range(transform) {
+                despawns.send(DespawnBullet(entity));
+            }
+        }
+
+        for (entity, transform, mut health) in enemies.iter_mut() {
+            if health.current <= 0.01 || !in_range(transform) {
+                continue;
+            }
+
+            health.current -= SPECIAL_DAMAGE;
+            if health.current <= 0.01 {
+                killed.send(EnemyKilled {
+                    enemy: entity,
+                    position: transform.translation,
+                    by: Some(player),
+                });
+            }
+        }
+    }
+}
+
+/// Flashes where a bullet grazed the player.
+#[derive(Component)]
+struct GrazeSpark {
+    timer: Timer,
+}
+
+const SPARK_DURATION: f32 = 0.3;
+
+fn spawn_graze_sparks(mut commands: Commands, mut reader: EventReader<Grazed>) {
+    for grazed in reader.read() {
+        commands.spawn((
+            GrazeSpark {
+                timer: Timer::from_seconds(SPARK_DURATION, TimerMode::Once),
+            },
+            StateScoped(InGame),
+            SpriteBundle {
+                sprite: Sprite {
+                    color: Color::WHITE,
+                    custom_size: Some(Vec2::splat(10.)),
+                    ..Default::default()
+                },
+                transform: Transform::from_translation(grazed.position.with_z(2.)),
+                ..Default::default()
+            },
+        ));
+    }
+}
+
+fn update_graze_sparks(
+    mut commands: Commands,
+    mut sparks: Query<(Entity, &mut GrazeSpark, &mut Sprite, &mut Transform)>,
+    time: Res<Time>,
+) {
+    for (entity, mut spark, mut sprite, mut transform) in sparks.iter_mut() {
+        if spark.timer.tick(time.delta()).finished() {
+            commands.entity(entity).despawn();
+            continue;
+        }
+
+        let t = spark.timer.fraction();
+        sprite.color.set_alpha(1. - t);
+        transform.scale = Vec3::splat(1. + 2. * t);
+    }
+}
+
+#[derive(Component)]
+struct GrazeMeterFill;
+
+const METER_WIDTH: f32 = 200.;
+
+fn spawn_graze_meter(mut commands: Commands, players: Query<(Entity, &Player)>) {
+    for (entity, player) in players.iter() {
+        commands
+            .spawn((
+                StateScoped(InGame),
+                NodeBundle {
+                    style: Style {
+                        position_type: PositionType::Absolute,
+                        left: Val::Px(20.),
+                        bottom: Val::Px(20. + HUD_ROW * player.0 as f32),
+                        width: Val::Px(METER_WIDTH),
+                        height: Val::Px(16.),
+                        ..Default::default()
+                    },
+                    background_color: Color::BLACK.into(),
+                    ..Default::default()
+                },
+            ))
+            .with_children(|parent| {
+                parent.spawn((
+                    GrazeMeterFill,
+                    PlayerHud(entity),
+                    NodeBundle {
+                        style: Style {
+                            width: Val::Px(0.),
+                            height: Val::Percent(100.),
+                            ..Default::default()
+                        },
+                        background_color: player.color().into(),
+                        ..Default::default()
+                    },
+                ));
+            });
+    }
+}
+
+fn update_graze_meter(
+    players: Query<(&Player, &GrazeMeter), Changed<GrazeMeter>>,
+    mut fills: Query<(&mut Style, &mut BackgroundColor, &PlayerHud), With<GrazeMeterFill>>,
+) {
+    for (mut style, mut color, hud) in fills.iter_mut() {
+        let Ok((player, meter)) = players.get(hud.0) else {
+            continue;
+        };
+
+        style.width = Val::Px(METER_WIDTH * meter.charge);
+        color.0 = if meter.full() {
+            Color::srgb(1., 0.85, 0.2)
+        } else {
+            player.color()
+        };
+    }
+}
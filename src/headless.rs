@@ -0,0 +1,296 @@
+use crate::{
+    bullet::{Bullet, Faction},
+    enemy::Enemy,
+    options::Options,
+    player::{
+        buffer_input, remove_input_maps, spawn_players, update_aim, Aim, Dead, FireAction, Lives,
+        MoveAction, Player,
+    },
+    replay::save_recording,
+    score::Scores,
+    state::{AppState, InGame, RequiredAssets},
+    wave::WaveDirector,
+    Health, ARENA,
+};
+use bevy::{
+    asset::AssetPlugin, hierarchy::HierarchyPlugin, input::InputPlugin, log::LogPlugin, prelude::*,
+    state::app::StatesPlugin, time::TimeUpdateStrategy, transform::TransformPlugin,
+};
+use leafwing_input_manager::{plugin::InputManagerSystem, prelude::*};
+use std::time::Duration;
+
+/// Runs the game without a window or renderer, as fast as possible, with `--headless`.
+///
+/// Every frame simulates a fixed 1/60th of a second. Unless a `--replay` drives the players, a
+/// simple bot plays every player instead. The app exits on game over or after `--frames <count>`
+/// frames of play, and logs how the run went.
+pub struct HeadlessPlugin;
+
+impl Plugin for HeadlessPlugin {
+    fn build(&self, app: &mut App) {
+        let options = app.world().resource::<Options>();
+        let max_frames = options.frames.unwrap_or(DEFAULT_FRAMES);
+        let bot = options.replay.is_none();
+
+        app.add_plugins((
+            MinimalPlugins,
+            LogPlugin::default(),
+            AssetPlugin::default(),
+            StatesPlugin,
+            InputPlugin,
+            TransformPlugin,
+            HierarchyPlugin,
+        ))
+        // Normally registered by the renderer, but gameplay code still builds meshes and materials.
+        .init_asset::<Mesh>()
+        .init_asset::<ColorMaterial>()
+        // Normally registered by the window plugin, aiming still reads it.
+        .add_event::<CursorMoved>()
+        .insert_resource(TimeUpdateStrategy::ManualDuration(Duration::from_secs_f64(
+            1. / 60.,
+        )))
+        .insert_resource(Simulation {
+            frames: 0,
+            max_frames,
+        })
+        .add_systems(Update, start_run.run_if(in_state(AppState::Title)))
+        .add_systems(
+            Last,
+            (
+                count_frame.run_if(in_state(AppState::Playing)),
+                finish_simulation,
+            )
+                .chain()
+                // Otherwise a `--record`ing could miss the exit, and never be saved.
+                .before(save_recording),
+        );
+
+        if bot {
+            app.add_systems(OnEnter(InGame), remove_input_maps.after(spawn_players))
+                .add_systems(
+                    PreUpdate,
+                    drive_bot
+                        .after(InputManagerSystem::ManualControl)
+                        .after(update_aim)
+                        .before(buffer_input),
+                );
+        }
+    }
+}
+
+/// Ten minutes of play.
+const DEFAULT_FRAMES: u64 = 60 * 60 * 10;
+
+#[derive(Resource)]
+struct Simulation {
+    frames: u64,
+    max_frames: u64,
+}
+
+fn start_run(
+    required: Res<RequiredAssets>,
+    asset_server: Res<AssetServer>,
+    mut next_state: ResMut<NextState<AppState>>,
+) {
+    if required.ready(&asset_server) {
+        next_state.set(AppState::Playing);
+    }
+}
+
+fn count_frame(mut simulation: ResMut<Simulation>) {
+    simulation.frames += 1;
+}
+
+fn finish_simulation(
+    simulation: Res<Simulation>,
+    state: Res<State<AppState>>,
+    players: Query<(&Player, &Health, &Lives)>,
+    director: Option<Res<WaveDirector>>,
+    scores: Res<Scores>,
+    mut writer: EventWriter<AppExit>,
+) {
+    let game_over = *state.get() == AppState::GameOver;
+    if !game_over && simulation.frames < simulation.max_frames {
+        return;
+    }
+
+    let waves = director.map_or(0, |director| director.completed);
+    if game_over {
+        info!(
+            "Simulated {} frames: game over after {} waves, score {}",
+            simulation.frames,
+            waves,
+            scores.total()
+        );
+    } else {
+        info!(
+            "Simulated {} frames: {} waves completed, score {}",
+            simulation.frames,
+            waves,
+            scores.total()
+        );
+        for (player, health, lives) in players.iter() {
+            info!(
+                "Player {}: health {}/{}, lives {}",
+                player.0 + 1,
+                health.current,
+                health.max,
+                lives.0
+            );
+        }
+    }
+
+    writer.send(AppExit::Success);
+}
+
+/// Enemy bullets closer than this push the bot away.
+const BOT_DODGE_RADIUS: f32 = 300.;
+/// Distance the bot tries to keep from the nearest enemy.
+const BOT_ENEMY_DISTANCE: f32 = 500.;
+
+/// Dodges nearby enemy bullets, keeps its distance from enemies and shoots the nearest one, for
+/// every living player.
+fn drive_bot(
+    mut players: Query<
+        (
+            &Transform,
+            &mut ActionState<MoveAction>,
+            &mut ActionState<FireAction>,
+            &mut Aim,
+        ),
+        (With<Player>, Without<Dead>),
+    >,
+    enemies: Query<&Transform, With<Enemy>>,
+    bullets: Query<(&Transform, &Bullet)>,
+) {
+    for (transform, mut moves, mut fire, mut aim) in players.iter_mut() {
+        let position = transform.translation.truncate();
+        let nearest_enemy = enemies
+            .iter()
+            .map(|enemy| enemy.translation.truncate())
+            .min_by(|a, b| {
+                a.distance_squared(position)
+                    .total_cmp(&b.distance_squared(position))
+            });
+
+        let mut direction = Vec2::ZERO;
+        for (bullet_transform, bullet) in bullets.iter() {
+            if !bullet.faction.can_damage(Faction::Player) {
+                continue;
+            }
+
+            let away = position - bullet_transform.translation.truncate();
+            let distance = away.length();
+            if distance < BOT_DODGE_RADIUS {
+                direction += away.normalize_or_zero() * (1. - distance / BOT_DODGE_RADIUS);
+            }
+        }
+
+        // Walk back in before reaching the edge of the arena, where bullets would corner it.
+        let inside = ARENA.inflate(-BOT_DODGE_RADIUS);
+        direction += (position.clamp(inside.min, inside.max) - position) / BOT_DODGE_RADIUS;
+
+        if let Some(enemy) = nearest_enemy {
+            let offset = position - enemy;
+            direction += offset.normalize_or_zero() * (BOT_ENEMY_DISTANCE - offset.length())
+                / BOT_ENEMY_DISTANCE;
+        }
+
+        let pressed = [
+            (MoveAction::Left, direction.x < -0.1),
+            (MoveAction::Right, direction.x > 0.1),
+            (MoveAction::Up, direction.y > 0.1),
+            (MoveAction::Down, direction.y < -0.1),
+        ];
+        for (action, pressed) in pressed {
+            if pressed {
+                moves.press(&action);
+            } else {
+                moves.release(&action);
+            }
+        }
+
+        // Firing happens on press, so tap the button every other frame.
+        aim.0 = nearest_enemy;
+        if nearest_enemy.is_some() && !fire.pressed(&FireAction) {
+            fire.press(&FireAction);
+        } else {
+            fire.release(&FireAction);
+        }
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::build_app;
+    use std::time::Instant;
+
+    /// A minute of play.
+    const FRAMES: u64 = 60 * 60;
+
+    /// How a run ended, with every player's current and max health and lives.
+    #[derive(Debug, PartialEq)]
+    pub(crate) struct Outcome {
+        pub frames: u64,
+        pub game_over: bool,
+        pub waves: u32,
+        pub score: u64,
+        pub players: Vec<(f32, f32, u32)>,
+    }
+
+    /// Runs the game headless with `options` until it exits.
+    pub(crate) fn run(options: Options) -> Outcome {
+        let mut app = build_app(Options {
+            headless: true,
+            ..options
+        });
+
+        // Assets load on other threads, so the run starts after a varying number of frames.
+        let start = Instant::now();
+        while app.should_exit().is_none() {
+            assert!(
+                start.elapsed() < Duration::from_secs(300),
+                "the run never finished"
+            );
+            app.update();
+        }
+
+        let world = app.world_mut();
+        let mut players = world.query::<(&Player, &Health, &Lives)>();
+        let mut players: Vec<_> = players
+            .iter(world)
+            .map(|(player, health, lives)| (player.0, (health.current, health.max, lives.0)))
+            .collect();
+        players.sort_by_key(|(index, ..)| *index);
+
+        Outcome {
+            frames: world.resource::<Simulation>().frames,
+            game_over: *world.resource::<State<AppState>>().get() == AppState::GameOver,
+            waves: world.resource::<WaveDirector>().completed,
+            score: world.resource::<Scores>().total(),
+            players: players.into_iter().map(|(_, player)| player).collect(),
+        }
+    }
+
+    fn simulate(seed: u64) -> Outcome {
+        run(Options {
+            seed: Some(seed),
+            frames: Some(FRAMES),
+            ..Default::default()
+        })
+    }
+
+    #[test]
+    fn bot_plays_a_deterministic_run() {
+        let outcome = simulate(1);
+
+        assert_eq!(outcome.frames, FRAMES);
+        assert!(!outcome.game_over, "the bot didn't last a minute");
+        // At least one kill, the bot fights back rather than only dodging.
+        assert!(outcome.score >= 100, "the bot never scored: {outcome:?}");
+
+        assert_eq!(simulate(1), outcome);
+        assert_ne!(simulate(2), outcome);
+    }
+}
@@ -0,0 +1,62 @@
+use bevy::{app::RunFixedMainLoop, prelude::*, time::run_fixed_main_schedule};
+
+pub struct InterpolationPlugin;
+
+impl Plugin for InterpolationPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(FixedFirst, restore_transforms)
+            .add_systems(FixedLast, snapshot_transforms)
+            .add_systems(
+                RunFixedMainLoop,
+                interpolate_transforms.after(run_fixed_main_schedule),
+            );
+    }
+}
+
+/// Renders the entity between its last two fixed steps, so that it moves smoothly at any framerate.
+///
+/// Inside the fixed schedules `Transform` holds the simulated value, everywhere else it holds the
+/// interpolated one. Insert a new `Interpolated` after teleporting the entity, so that it doesn't
+/// slide there.
+#[derive(Component, Debug, Clone, Copy)]
+pub struct Interpolated {
+    previous: (Vec3, Quat),
+    current: (Vec3, Quat),
+}
+
+impl Interpolated {
+    pub fn new(transform: &Transform) -> Self {
+        let state = (transform.translation, transform.rotation);
+        Self {
+            previous: state,
+            current: state,
+        }
+    }
+}
+
+fn restore_transforms(mut entities: Query<(&mut Transform, &Interpolated)>) {
+    for (mut transform, interpolated) in entities.iter_mut() {
+        (transform.translation, transform.rotation) = interpolated.current;
+    }
+}
+
+fn snapshot_transforms(mut entities: Query<(&Transform, &mut Interpolated)>) {
+    for (transform, mut interpolated) in entities.iter_mut() {
+        interpolated.previous = interpolated.current;
+        interpolated.current = (transform.translation, transform.rotation);
+    }
+}
+
+fn interpolate_transforms(
+    mut entities: Query<(&mut Transform, &Interpolated)>,
+    time: Res<Time<Fixed>>,
+) {
+    let t = time.overstep_fraction();
+
+    for (mut transform, interpolated) in entities.iter_mut() {
+        let (previous_translation, previous_rotation) = interpolated.previous;
+        let (current_translation, current_rotation) = interpolated.current;
+        transform.translation = previous_translation.lerp(current_translation, t);
+        transform.rotation = previous_rotation.slerp(current_rotation, t);
+    }
+}
@@ -1,5 +1,7 @@
+#![allow(clippy::type_complexity)]
+
 use bevy::{
-    input::{keyboard::KeyboardInput, ButtonState},
+    ecs::schedule::ExecutorKind,
     math::VectorSpace,
     prelude::*,
     reflect::List,
@@ -11,22 +13,69 @@ use bevy::{
     sprite::Mesh2dHandle,
     window::{PresentMode, PrimaryWindow},
 };
+use bullet::InactiveBullet;
 use camera::{MainCamera, PlayerCamera};
-use enemy::spawn_enemy;
 use iyes_perf_ui::{entries::PerfUiBundle, PerfUiPlugin};
 use leafwing_input_manager::prelude::*;
+use options::Options;
 use player::{FireAction, Player};
 use rand::Rng;
+use state::AppState;
 use std::f32::consts::PI;
 
+mod bindings;
 mod bullet;
 mod camera;
+mod collision;
+mod coop;
+mod dash;
+mod emitter;
 mod enemy;
+mod focus;
+mod graze;
+mod headless;
+mod interpolation;
+mod options;
+mod pickup;
 mod player;
+mod replay;
+mod rng;
+mod ron_asset;
+mod score;
+mod spatial;
+mod state;
+mod steering;
+mod storage;
+mod wave;
+mod weapon;
 
 fn main() {
-    App::default()
-        .add_plugins(DefaultPlugins.set(WindowPlugin {
+    let options = match Options::from_args(std::env::args().skip(1)) {
+        Ok(options) => options,
+        Err(err) => {
+            eprintln!("{err}");
+            std::process::exit(2);
+        }
+    };
+
+    if options.bench_collision {
+        spatial::run_benchmark();
+        return;
+    }
+
+    build_app(options).run();
+}
+
+/// Assembles the game without running it, in a window or headless depending on `options`.
+pub fn build_app(options: Options) -> App {
+    let mut app = App::default();
+    let headless = options.headless;
+    app.insert_resource(options);
+
+    if headless {
+        app.add_plugins(headless::HeadlessPlugin);
+    } else {
+        app.add_plugins(DefaultPlugins.set(WindowPlugin {
             primary_window: Some(Window {
                 title: "hypernova".into(),
                 resolution: [1920., 1080.].into(),
@@ -41,19 +90,81 @@ fn main() {
             bevy::diagnostic::EntityCountDiagnosticsPlugin,
             bevy::diagnostic::FrameTimeDiagnosticsPlugin,
         ))
-        .add_plugins((
-            camera::CameraPlugin,
-            player::PlayerPlugin,
-            bullet::BulletPlugin,
-            enemy::EnemyPlugin,
-        ))
-        .add_systems(Startup, setup)
-        .add_systems(
-            Update,
-            (exit_on_esc, update_health_bar, despawn_with_no_health), // .chain(),
+        .add_systems(Startup, setup);
+    }
+
+    app.add_plugins((
+        state::StatePlugin,
+        rng::RngPlugin,
+        replay::ReplayPlugin,
+        camera::CameraPlugin,
+        player::PlayerPlugin,
+        bullet::BulletPlugin,
+        enemy::EnemyPlugin,
+        emitter::EmitterPlugin,
+        spatial::SpatialPlugin,
+        collision::CollisionPlugin,
+        interpolation::InterpolationPlugin,
+        weapon::WeaponPlugin,
+        pickup::PickupPlugin,
+        score::ScorePlugin,
+        graze::GrazePlugin,
+    ))
+    .add_plugins(bindings::BindingsPlugin)
+    .configure_sets(
+        FixedUpdate,
+        (
+            GameSet::Control,
+            GameSet::Spawn,
+            GameSet::Damage,
+            GameSet::Death,
+            GameSet::Cleanup,
         )
-        .add_systems(FixedPostUpdate, (apply_friction, update_velocity))
-        .run();
+            .chain()
+            .run_if(in_state(AppState::Playing)),
+    )
+    .add_systems(Update, update_health_bar)
+    .add_systems(FixedUpdate, despawn_with_no_health.in_set(GameSet::Death))
+    .add_systems(
+        FixedPostUpdate,
+        (apply_friction, update_velocity).run_if(in_state(AppState::Playing)),
+    )
+    // A replay only plays back the same run if gameplay systems that aren't ordered against each
+    // other still run in the same order every step.
+    .edit_schedule(FixedUpdate, run_in_order)
+    .edit_schedule(FixedPostUpdate, run_in_order);
+
+    app
+}
+
+/// Runs the systems of `schedule` one at a time, always in the same order.
+fn run_in_order(schedule: &mut Schedule) {
+    schedule.set_executor_kind(ExecutorKind::SingleThreaded);
+}
+
+/// The playing field, centered on the origin.
+///
+/// Players can't leave it, enemies spawn inside it, and the camera doesn't show past it.
+pub const ARENA: Rect = Rect {
+    min: Vec2::new(-2500., -2500.),
+    max: Vec2::new(2500., 2500.),
+};
+
+/// Order of the gameplay systems within a fixed step, which only run while playing.
+///
+/// Movement, the spatial hash and collision detection follow in `FixedPostUpdate`, so damage is
+/// dealt from the contacts of the previous step.
+#[derive(SystemSet, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum GameSet {
+    /// Reads the buffered input and runs behaviors: steering, emitters, waves, firing.
+    Control,
+    /// Turns `SpawnBullet` events into bullets.
+    Spawn,
+    Damage,
+    /// Reacts to health running out.
+    Death,
+    /// Returns bullets to their pools and checks for the end of the run.
+    Cleanup,
 }
 
 #[derive(Component)]
@@ -71,14 +182,6 @@ impl Health {
 #[derive(Component)]
 struct HealthBar;
 
-fn exit_on_esc(mut reader: EventReader<KeyboardInput>, mut writer: EventWriter<AppExit>) {
-    for input in reader.read() {
-        if input.state == ButtonState::Pressed && input.key_code == KeyCode::Escape {
-            writer.send(AppExit::Success);
-        }
-    }
-}
-
 fn setup(mut commands: Commands) {
     commands.spawn(PerfUiBundle::default());
 }
@@ -96,7 +199,10 @@ impl Velocity {
     }
 }
 
-fn apply_friction(mut entities: Query<(&mut Velocity, &Friction)>, time: Res<Time>) {
+fn apply_friction(
+    mut entities: Query<(&mut Velocity, &Friction), Without<InactiveBullet>>,
+    time: Res<Time>,
+) {
     for (mut velocity, friction) in entities.iter_mut() {
         let dir = velocity.0.normalize_or_zero();
         let friction =
@@ -147,22 +253,23 @@ fn update_health_bar(
     }
 }
 
-fn update_velocity(mut entities: Query<(&mut Transform, &Velocity)>, time: Res<Time>) {
+fn update_velocity(
+    mut entities: Query<(&mut Transform, &Velocity), Without<InactiveBullet>>,
+    time: Res<Time>,
+) {
     for (mut transform, velocity) in entities.iter_mut() {
         transform.translation += velocity.0 * time.delta_seconds();
     }
 }
 
+/// The player is never despawned, see `player::kill_player` instead.
 fn despawn_with_no_health(
     mut commands: Commands,
-    entities: Query<(Entity, &Health)>,
-    meshes: ResMut<Assets<Mesh>>,
+    entities: Query<(Entity, &Health), Without<Player>>,
 ) {
     for (entity, health) in entities.iter() {
         if health.current <= 0.01 {
             commands.entity(entity).despawn_recursive();
-            spawn_enemy(commands, meshes);
-            return;
         }
     }
 }
@@ -0,0 +1,96 @@
+use bevy::prelude::*;
+use std::path::PathBuf;
+
+/// Command line options, parsed once by `main` and read by the plugins they configure.
+///
+/// Inserted before any plugin is added, so that plugins can read it while they are built.
+#[derive(Resource, Debug, Clone, Default)]
+pub struct Options {
+    /// `--headless`: run without a window or renderer, see `HeadlessPlugin`.
+    pub headless: bool,
+    /// `--frames <count>`: frames of play a headless run simulates before it exits.
+    pub frames: Option<u64>,
+    /// `--seed <u64>`: play the same seed every run.
+    pub seed: Option<u64>,
+    /// `--record <path>`: record every run to `path`.
+    pub record: Option<PathBuf>,
+    /// `--replay <path>`: play back the run recorded at `path`.
+    pub replay: Option<PathBuf>,
+    /// `--bench-collision`: run the collision benchmark instead of the game.
+    pub bench_collision: bool,
+}
+
+impl Options {
+    /// Parses the arguments after the program name.
+    pub fn from_args(args: impl IntoIterator<Item = String>) -> Result<Self, String> {
+        let mut options = Self::default();
+        let mut args = args.into_iter();
+
+        while let Some(arg) = args.next() {
+            let mut value = |expected: &str| {
+                args.next()
+                    .ok_or_else(|| format!("`{arg}` expects {expected}."))
+            };
+
+            match arg.as_str() {
+                "--headless" => options.headless = true,
+                "--bench-collision" => options.bench_collision = true,
+                "--frames" => {
+                    let frames = value("a frame count")?;
+                    options.frames = Some(frames.parse().map_err(|_| {
+                        format!("Invalid `--frames` `{frames}`, expected an unsigned integer.")
+                    })?);
+                }
+                "--seed" => {
+                    let seed = value("a seed")?;
+                    options.seed = Some(seed.parse().map_err(|_| {
+                        format!("Invalid `--seed` `{seed}`, expected an unsigned integer.")
+                    })?);
+                }
+                "--record" => options.record = Some(value("a path")?.into()),
+                "--replay" => options.replay = Some(value("a path")?.into()),
+                _ => return Err(format!("Unknown argument `{arg}`.")),
+            }
+        }
+
+        Ok(options)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Result<Options, String> {
+        Options::from_args(args.iter().map(|arg| arg.to_string()))
+    }
+
+    #[test]
+    fn parses_every_option() {
+        let options = parse(&[
+            "--headless",
+            "--frames",
+            "600",
+            "--seed",
+            "7",
+            "--record",
+            "run.hnrp",
+        ])
+        .unwrap();
+
+        assert!(options.headless);
+        assert_eq!(options.frames, Some(600));
+        assert_eq!(options.seed, Some(7));
+        assert_eq!(options.record, Some(PathBuf::from("run.hnrp")));
+        assert_eq!(options.replay, None);
+        assert!(!options.bench_collision);
+    }
+
+    #[test]
+    fn rejects_invalid_arguments() {
+        assert!(parse(&["--headles"]).is_err());
+        assert!(parse(&["run.hnrp"]).is_err());
+        assert!(parse(&["--seed"]).is_err());
+        assert!(parse(&["--frames", "-1"]).is_err());
+    }
+}
@@ -0,0 +1,398 @@
+use crate::{
+    build_mesh,
+    collision::{Collider, ColliderShape, Contacts, Layers},
+    enemy::EnemyKilled,
+    interpolation::Interpolated,
+    player::{Dead, Player, PLAYER_RADIUS},
+    rng::GameRng,
+    state::InGame,
+    Friction, GameSet, Health, Velocity,
+};
+use bevy::{prelude::*, sprite::Mesh2dHandle, utils::HashMap};
+use rand::{seq::SliceRandom, Rng};
+use std::{mem::discriminant, time::Duration};
+
+pub struct PickupPlugin;
+
+impl Plugin for PickupPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(Startup, init_pickup_assets)
+            .add_systems(
+                FixedUpdate,
+                (
+                    (tick_modifiers, attract_pickups).in_set(GameSet::Control),
+                    collect_pickups.in_set(GameSet::Damage),
+                    drop_pickups
+                        .before(crate::despawn_with_no_health)
+                        .in_set(GameSet::Death),
+                    expire_pickups.in_set(GameSet::Cleanup),
+                ),
+            )
+            .add_systems(Update, (add_shield_bubble, update_shield_bubbles).chain());
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum PickupKind {
+    /// Heals `PICKUP_HEAL` right away.
+    Health,
+    /// Fires faster for a while.
+    FireRate,
+    /// One more bullet per shot, for the rest of the run.
+    ExtraProjectile,
+    /// Bullets don't hurt for a while.
+    Shield,
+    /// Kills are worth more for a while.
+    ScoreMultiplier,
+}
+
+impl PickupKind {
+    fn color(self) -> Color {
+        match self {
+            Self::Health => Color::srgb(0.3, 1., 0.4),
+            Self::FireRate => Color::srgb(1., 0.55, 0.1),
+            Self::ExtraProjectile => Color::srgb(0.2, 0.9, 1.),
+            Self::Shield => Color::srgb(0.3, 0.45, 1.),
+            Self::ScoreMultiplier => Color::srgb(1., 0.85, 0.2),
+        }
+    }
+}
+
+/// Lies around until a player collects it or its lifetime runs out.
+#[derive(Component, Debug, Clone)]
+pub struct Pickup {
+    pub kind: PickupKind,
+    lifetime: Timer,
+}
+
+/// What an enemy may drop when it dies.
+#[derive(Component, Debug, Clone)]
+pub struct DropTable {
+    /// Odds of dropping anything at all, between 0 and 1.
+    chance: f32,
+    /// Relative odds of each kind, once something drops.
+    entries: Vec<(PickupKind, u32)>,
+}
+
+impl DropTable {
+    pub fn new(chance: f32) -> Self {
+        Self {
+            chance,
+            entries: Vec::new(),
+        }
+    }
+
+    pub fn with(mut self, kind: PickupKind, weight: u32) -> Self {
+        self.entries.push((kind, weight));
+        self
+    }
+
+    pub fn roll(&self, rng: &mut impl Rng) -> Option<PickupKind> {
+        if rng.gen::<f32>() >= self.chance {
+            return None;
+        }
+
+        self.entries
+            .choose_weighted(rng, |(_, weight)| *weight)
+            .ok()
+            .map(|(kind, _)| *kind)
+    }
+}
+
+/// Changes to a player's stats, from pickups.
+#[derive(Component, Debug, Default, Clone)]
+pub struct Modifiers(Vec<Modifier>);
+
+#[derive(Debug, Clone)]
+struct Modifier {
+    effect: Effect,
+    /// Permanent without one.
+    timer: Option<Timer>,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum Effect {
+    /// Multiplies the fire rate of every weapon.
+    FireRate(f32),
+    ExtraProjectiles(u32),
+    Shield,
+    ScoreMultiplier(f32),
+}
+
+impl Modifiers {
+    /// Adds a modifier that lasts `seconds`, or forever without them.
+    ///
+    /// Picking up a timed effect that is already active refreshes it instead of stacking.
+    pub fn add(&mut self, effect: Effect, seconds: Option<f32>) {
+        let timer = seconds.map(|seconds| Timer::from_seconds(seconds, TimerMode::Once));
+
+        if timer.is_some() {
+            if let Some(modifier) = self.0.iter_mut().find(|modifier| {
+                modifier.timer.is_some() && discriminant(&modifier.effect) == discriminant(&effect)
+            }) {
+                *modifier = Modifier { effect, timer };
+                return;
+            }
+        }
+
+        self.0.push(Modifier { effect, timer });
+    }
+
+    fn tick(&mut self, delta: Duration) {
+        self.0.retain_mut(|modifier| {
+            !modifier
+                .timer
+                .as_mut()
+                .is_some_and(|timer| timer.tick(delta).finished())
+        });
+    }
+
+    pub fn fire_rate(&self) -> f32 {
+        self.effects()
+            .filter_map(|effect| match effect {
+                Effect::FireRate(rate) => Some(rate),
+                _ => None,
+            })
+            .product()
+    }
+
+    pub fn extra_projectiles(&self) -> u32 {
+        self.effects()
+            .filter_map(|effect| match effect {
+                Effect::ExtraProjectiles(count) => Some(count),
+                _ => None,
+            })
+            .sum::<u32>()
+            .min(MAX_EXTRA_PROJECTILES)
+    }
+
+    pub fn shielded(&self) -> bool {
+        self.effects()
+            .any(|effect| matches!(effect, Effect::Shield))
+    }
+
+    pub fn score_multiplier(&self) -> f32 {
+        self.effects()
+            .filter_map(|effect| match effect {
+                Effect::ScoreMultiplier(multiplier) => Some(multiplier),
+                _ => None,
+            })
+            .product()
+    }
+
+    fn effects(&self) -> impl Iterator<Item = Effect> + '_ {
+        self.0.iter().map(|modifier| modifier.effect)
+    }
+}
+
+const PICKUP_RADIUS: f32 = 15.;
+/// Seconds before an uncollected pickup disappears.
+const PICKUP_LIFETIME: f32 = 10.;
+/// Pickups within this distance of a player drift towards them.
+const PICKUP_MAGNET_RADIUS: f32 = 300.;
+const PICKUP_MAGNET_ACCELERATION: f32 = 4000.;
+const PICKUP_MAX_SPEED: f32 = 900.;
+const PICKUP_FRICTION: f32 = 600.;
+
+const PICKUP_HEAL: f32 = 3.;
+const MAX_EXTRA_PROJECTILES: u32 = 4;
+
+#[derive(Resource)]
+struct PickupAssets {
+    mesh: Mesh2dHandle,
+    materials: HashMap<PickupKind, Handle<ColorMaterial>>,
+    shield_mesh: Mesh2dHandle,
+    shield_material: Handle<ColorMaterial>,
+}
+
+fn init_pickup_assets(
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+) {
+    let kinds = [
+        PickupKind::Health,
+        PickupKind::FireRate,
+        PickupKind::ExtraProjectile,
+        PickupKind::Shield,
+        PickupKind::ScoreMultiplier,
+    ];
+
+    commands.insert_resource(PickupAssets {
+        mesh: meshes.add(build_mesh(PICKUP_RADIUS, 6)).into(),
+        materials: kinds
+            .into_iter()
+            .map(|kind| (kind, materials.add(kind.color())))
+            .collect(),
+        shield_mesh: meshes.add(build_mesh(PLAYER_RADIUS * 1.4, 24)).into(),
+        shield_material: materials.add(PickupKind::Shield.color().with_alpha(0.35)),
+    });
+}
+
+fn spawn_pickup(commands: &mut Commands, assets: &PickupAssets, kind: PickupKind, position: Vec3) {
+    let transform = Transform::from_translation(position.with_z(-1.));
+
+    commands.spawn((
+        Pickup {
+            kind,
+            lifetime: Timer::from_seconds(PICKUP_LIFETIME, TimerMode::Once),
+        },
+        StateScoped(InGame),
+        ColorMesh2dBundle {
+            mesh: assets.mesh.clone(),
+            material: assets.materials[&kind].clone(),
+            transform,
+            ..Default::default()
+        },
+        Interpolated::new(&transform),
+        Collider::new(
+            ColliderShape::Circle {
+                radius: PICKUP_RADIUS,
+            },
+            Layers::PICKUP,
+            Layers::PLAYER,
+        ),
+        Velocity::default(),
+        Friction(PICKUP_FRICTION),
+    ));
+}
+
+/// Rolls the drop table of every killed enemy, and drops the pickup where it died.
+fn drop_pickups(
+    mut commands: Commands,
+    mut reader: EventReader<EnemyKilled>,
+    tables: Query<&DropTable>,
+    assets: Res<PickupAssets>,
+    mut rng: ResMut<GameRng>,
+) {
+    for killed in reader.read() {
+        let Ok(table) = tables.get(killed.enemy) else {
+            continue;
+        };
+
+        if let Some(kind) = table.roll(&mut rng.gameplay) {
+            spawn_pickup(&mut commands, &assets, kind, killed.position);
+        }
+    }
+}
+
+fn attract_pickups(
+    mut pickups: Query<(&Transform, &mut Velocity), With<Pickup>>,
+    players: Query<&Transform, (With<Player>, Without<Dead>)>,
+    time: Res<Time>,
+) {
+    for (transform, mut velocity) in pickups.iter_mut() {
+        let position = transform.translation.truncate();
+        let nearest = players
+            .iter()
+            .map(|player| player.translation.truncate() - position)
+            .filter(|offset| offset.length_squared() < PICKUP_MAGNET_RADIUS * PICKUP_MAGNET_RADIUS)
+            .min_by(|a, b| a.length_squared().total_cmp(&b.length_squared()));
+
+        if let Some(offset) = nearest {
+            velocity.add_velocity_clamped(
+                offset.normalize_or_zero().extend(0.)
+                    * PICKUP_MAGNET_ACCELERATION
+                    * time.delta_seconds(),
+                PICKUP_MAX_SPEED,
+            );
+        }
+    }
+}
+
+/// Reads every overlap rather than new ones, so that a player revived on top of a pickup still
+/// collects it.
+fn collect_pickups(
+    mut commands: Commands,
+    contacts: Res<Contacts>,
+    pickups: Query<&Pickup>,
+    mut players: Query<(&mut Health, &mut Modifiers), (With<Player>, Without<Dead>)>,
+    mut collected: Local<Vec<Entity>>,
+) {
+    collected.clear();
+
+    for (entity, target) in contacts.pairs() {
+        let (Ok(pickup), Ok((mut health, mut modifiers))) =
+            (pickups.get(entity), players.get_mut(target))
+        else {
+            continue;
+        };
+
+        // Two players can touch the same pickup on the same step.
+        if collected.contains(&entity) {
+            continue;
+        }
+        collected.push(entity);
+
+        match pickup.kind {
+            PickupKind::Health => {
+                health.current = (health.current + PICKUP_HEAL).min(health.max);
+            }
+            PickupKind::FireRate => modifiers.add(Effect::FireRate(1.5), Some(10.)),
+            PickupKind::ExtraProjectile => modifiers.add(Effect::ExtraProjectiles(1), None),
+            PickupKind::Shield => modifiers.add(Effect::Shield, Some(5.)),
+            PickupKind::ScoreMultiplier => modifiers.add(Effect::ScoreMultiplier(2.), Some(10.)),
+        }
+
+        commands.entity(entity).despawn_recursive();
+    }
+}
+
+fn expire_pickups(
+    mut commands: Commands,
+    mut pickups: Query<(Entity, &mut Pickup)>,
+    time: Res<Time>,
+) {
+    for (entity, mut pickup) in pickups.iter_mut() {
+        if pickup.lifetime.tick(time.delta()).finished() {
+            commands.entity(entity).despawn_recursive();
+        }
+    }
+}
+
+fn tick_modifiers(mut players: Query<&mut Modifiers>, time: Res<Time>) {
+    for mut modifiers in players.iter_mut() {
+        modifiers.tick(time.delta());
+    }
+}
+
+/// Shown around the player while shielded.
+#[derive(Component)]
+struct ShieldBubble;
+
+fn add_shield_bubble(
+    mut commands: Commands,
+    players: Query<Entity, Added<Modifiers>>,
+    assets: Res<PickupAssets>,
+) {
+    for player in players.iter() {
+        commands.entity(player).with_children(|parent| {
+            parent.spawn((
+                ShieldBubble,
+                ColorMesh2dBundle {
+                    mesh: assets.shield_mesh.clone(),
+                    material: assets.shield_material.clone(),
+                    transform: Transform::from_xyz(0., 0., -0.5),
+                    visibility: Visibility::Hidden,
+                    ..Default::default()
+                },
+            ));
+        });
+    }
+}
+
+fn update_shield_bubbles(
+    players: Query<(&Modifiers, &Children)>,
+    mut bubbles: Query<&mut Visibility, With<ShieldBubble>>,
+) {
+    for (modifiers, children) in players.iter() {
+        let mut bubbles = bubbles.iter_many_mut(children);
+        while let Some(mut visibility) = bubbles.fetch_next() {
+            *visibility = if modifiers.shielded() {
+                Visibility::Inherited
+            } else {
+                Visibility::Hidden
+            };
+        }
+    }
+}
@@ -1,11 +1,23 @@
-use bevy::{prelude::*, window::PrimaryWindow};
-use leafwing_input_manager::prelude::*;
+use bevy::{prelude::*, sprite::Mesh2dHandle, window::PrimaryWindow};
+use leafwing_input_manager::{plugin::InputManagerSystem, prelude::*};
 
 use crate::{
-    add_health_bar, build_mesh,
-    bullet::{Bullet, BulletType, SpawnBullet},
-    camera::MainCamera,
-    Friction, Health, Velocity,
+    add_health_bar,
+    bindings::{Bindings, Devices},
+    build_mesh,
+    bullet::{Faction, SpawnBullet},
+    camera::{CameraTarget, MainCamera, PlayerCamera, ScreenShake},
+    collision::{Collider, ColliderShape, Layers},
+    coop::CoopPlugin,
+    dash::{Dash, DashPlugin},
+    focus::{FocusPlugin, FOCUS_ACCELERATION, FOCUS_MAX_SPEED, FOCUS_SPREAD},
+    graze::{GrazeMeter, SpecialAction},
+    interpolation::Interpolated,
+    pickup::Modifiers,
+    spatial::rebuild_spatial_hash,
+    state::{AppState, InGame},
+    weapon::{cycle_weapons, spawn_loadout, Weapon, WeaponAction, WeaponInventory},
+    Friction, GameSet, Health, Velocity, ARENA,
 };
 
 pub struct PlayerPlugin;
@@ -15,112 +27,743 @@ impl Plugin for PlayerPlugin {
         app.add_plugins((
             InputManagerPlugin::<MoveAction>::default(),
             InputManagerPlugin::<FireAction>::default(),
+            InputManagerPlugin::<StickAction>::default(),
         ))
-        .add_systems(Startup, spawn_player)
-        .add_systems(Update, (move_player, fire_bullets));
+        .add_plugins((CoopPlugin, DashPlugin, FocusPlugin))
+        .add_event::<PlayerDied>()
+        .add_event::<PlayerRespawned>()
+        .init_resource::<PlayerCount>()
+        .add_systems(OnEnter(InGame), (spawn_players, spawn_lives_text).chain())
+        .add_systems(
+            PreUpdate,
+            (update_aim, buffer_input.run_if(in_state(AppState::Playing)))
+                .chain()
+                .after(InputManagerSystem::Update),
+        )
+        .add_systems(
+            FixedUpdate,
+            (
+                (move_player, fire_bullets.after(cycle_weapons)).in_set(GameSet::Control),
+                (kill_player, respawn_player, blink_invulnerable)
+                    .chain()
+                    .in_set(GameSet::Death),
+            ),
+        )
+        .add_systems(
+            FixedPostUpdate,
+            keep_players_in_arena
+                .after(crate::update_velocity)
+                .before(rebuild_spatial_hash)
+                .run_if(in_state(AppState::Playing)),
+        )
+        .add_systems(
+            Update,
+            (update_lives_text, (spawn_bursts, update_bursts).chain())
+                .run_if(in_state(AppState::Playing)),
+        );
+    }
+}
+
+/// One of the players, numbered from 0.
+#[derive(Component, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Player(pub usize);
+
+impl Player {
+    /// Tints the player and their HUD, so that players can tell theirs apart.
+    pub fn color(&self) -> Color {
+        PLAYER_COLORS[self.0 % MAX_PLAYERS]
+    }
+}
+
+pub const MAX_PLAYERS: usize = 2;
+const PLAYER_COLORS: [Color; MAX_PLAYERS] = [Color::WHITE, Color::srgb(0.4, 0.8, 1.)];
+
+/// Players in the next run, picked on the title screen. Between 1 and `MAX_PLAYERS`.
+#[derive(Resource, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PlayerCount(pub usize);
+
+impl Default for PlayerCount {
+    fn default() -> Self {
+        Self(1)
     }
 }
 
+/// A HUD element showing the state of this player.
+///
+/// Every player gets their own, and the HUDs of later players are moved `HUD_ROW` further from
+/// the edge of the screen per row of the HUDs before them.
+#[derive(Component, Debug, Clone, Copy)]
+pub struct PlayerHud(pub Entity);
+
+pub const HUD_ROW: f32 = 40.;
+
+/// Where enemies go: the nearest living player, or the nearest dead one once all are dead.
+pub fn nearest_player<'a>(
+    players: impl IntoIterator<Item = (&'a Transform, bool)>,
+    from: Vec3,
+) -> Option<Vec3> {
+    players
+        .into_iter()
+        .map(|(transform, dead)| {
+            let position = transform.translation;
+            (dead, position.distance_squared(from), position)
+        })
+        .min_by(|a, b| a.0.cmp(&b.0).then(a.1.total_cmp(&b.1)))
+        .map(|(.., position)| position)
+}
+
+/// World position the player is aiming at, if any.
+///
+/// Written from the cursor or the right stick every frame, unless something else drives the
+/// player, like a replay.
+#[derive(Component, Debug, Default, Clone, Copy)]
+pub struct Aim(pub Option<Vec2>);
+
+/// What the player aims with, switched to whichever was used last.
+#[derive(Component, Debug, Default, Clone, Copy, PartialEq)]
+pub enum AimMode {
+    #[default]
+    Mouse,
+    /// Towards the last direction the right stick was pushed in, even once it is let go.
+    Gamepad(Vec2),
+}
+
+/// Input gathered every frame and consumed by the next fixed step.
+///
+/// Frames and fixed steps don't line up, so a press can't be read from the `ActionState` in
+/// `FixedUpdate` without sometimes being missed or seen twice.
+#[derive(Component, Debug, Default, Clone, Copy)]
+pub struct BufferedInput {
+    /// Sum of the held directions and the left stick, not normalized.
+    ///
+    /// Shorter than 1 only when the stick is partly pushed, which slows the player down.
+    pub movement: Vec2,
+    /// Fire was pressed since the last step that could fire.
+    pub fire: bool,
+    /// Fire is held, for automatic weapons.
+    pub firing: bool,
+    /// Weapons to cycle forward by, or backward when negative.
+    pub cycle: i32,
+    /// The special was pressed since the last step.
+    pub special: bool,
+    /// Focus is held.
+    pub focus: bool,
+    /// Dash was pressed since the last step.
+    pub dash: bool,
+}
+
+/// Remaining lives, including the current one.
+#[derive(Component, Debug, Clone, Copy)]
+pub struct Lives(pub u32);
+
+/// The player is dead, waiting to respawn, to be revived by another player or for the game over
+/// screen once out of lives.
+#[derive(Component, Debug, Clone)]
+pub struct Dead {
+    timer: Timer,
+    /// Seconds another player spent reviving this one.
+    pub revive: f32,
+}
+
+impl Dead {
+    pub fn finished(&self) -> bool {
+        self.timer.finished()
+    }
+}
+
+/// Bullets pass through the player while the timer runs, and the player blinks.
+#[derive(Component, Debug, Clone)]
+pub struct Invulnerable {
+    timer: Timer,
+    blink: Timer,
+}
+
+impl Invulnerable {
+    pub fn from_seconds(seconds: f32) -> Self {
+        Self {
+            timer: Timer::from_seconds(seconds, TimerMode::Once),
+            blink: Timer::from_seconds(PLAYER_BLINK_INTERVAL, TimerMode::Repeating),
+        }
+    }
+}
+
+/// Sent when the player's health runs out.
+#[derive(Event, Debug, Clone, Copy)]
+pub struct PlayerDied {
+    pub player: Entity,
+    pub position: Vec3,
+    /// Lives left after this death. The run is over at 0.
+    pub lives: u32,
+}
+
+/// Sent when the player comes back after a death, at `position`.
+#[derive(Event, Debug, Clone, Copy)]
+pub struct PlayerRespawned {
+    pub player: Entity,
+    pub position: Vec3,
+}
+
 #[derive(Component)]
-pub struct Player;
+struct LivesText;
 
 #[derive(Debug, Actionlike, PartialEq, Eq, Hash, Clone, Copy, Reflect)]
-enum MoveAction {
+pub enum MoveAction {
     Left,
     Right,
     Up,
     Down,
+    /// Held for slower, precise movement and a tighter spread.
+    Focus,
+    Dash,
 }
 
 #[derive(Debug, Actionlike, PartialEq, Eq, Hash, Clone, Copy, Reflect)]
 pub struct FireAction;
 
-fn spawn_player(mut commands: Commands, mut meshes: ResMut<Assets<Mesh>>) {
-    let move_input_map = InputMap::new([
-        (MoveAction::Left, KeyCode::KeyA),
-        (MoveAction::Right, KeyCode::KeyD),
-        (MoveAction::Up, KeyCode::KeyW),
-        (MoveAction::Down, KeyCode::KeyS),
-    ]);
+/// The analog sticks of a gamepad, for twin-stick movement and aiming.
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy, Reflect)]
+pub enum StickAction {
+    Move,
+    Aim,
+}
 
-    let fire_input_map = InputMap::new([(FireAction, MouseButton::Left)]);
+impl Actionlike for StickAction {
+    fn input_control_kind(&self) -> InputControlKind {
+        InputControlKind::DualAxis
+    }
+}
 
-    let mesh = build_mesh(50., 8);
+/// Alone, the player can use every device. Together, the first player keeps the keyboard and
+/// mouse and the second takes the gamepad.
+pub fn spawn_players(
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+    asset_server: Res<AssetServer>,
+    bindings: Res<Bindings>,
+    count: Res<PlayerCount>,
+) {
+    let mesh: Mesh2dHandle = meshes.add(build_mesh(PLAYER_RADIUS, 8)).into();
 
-    let player = commands
-        .spawn((
-            Player,
-            ColorMesh2dBundle {
-                mesh: meshes.add(mesh).into(),
-                transform: Transform::from_translation(Vec3::new(0., 0., 0.)),
-                ..Default::default()
-            },
-            Health::from_max(10.),
-            Velocity(Vec3::ZERO),
-            Friction(PLAYER_FRICTION),
-        ))
-        .insert(InputManagerBundle::with_map(move_input_map))
-        .insert(InputManagerBundle::with_map(fire_input_map))
-        .id();
+    for index in 0..count.0 {
+        let player = Player(index);
+        let devices = match (count.0, index) {
+            (1, _) => Devices::All,
+            (_, 0) => Devices::KeyboardMouse,
+            _ => Devices::Gamepad,
+        };
+        let aim_mode = if devices.keyboard_mouse() {
+            AimMode::Mouse
+        } else {
+            AimMode::Gamepad(Vec2::Y)
+        };
+        let stick_input_map = if devices.gamepad() {
+            InputMap::default()
+                .with_dual_axis(StickAction::Move, GamepadStick::LEFT)
+                .with_dual_axis(StickAction::Aim, GamepadStick::RIGHT)
+        } else {
+            InputMap::default()
+        };
+        let spawn = spawn_position(index, count.0);
+
+        let entity = commands
+            .spawn((
+                player,
+                CameraTarget,
+                StateScoped(InGame),
+                ColorMesh2dBundle {
+                    mesh: mesh.clone(),
+                    material: materials.add(player.color()),
+                    transform: Transform::from_translation(spawn),
+                    ..Default::default()
+                },
+                Health::from_max(10.),
+                Lives(PLAYER_LIVES),
+                Aim::default(),
+                BufferedInput::default(),
+                Modifiers::default(),
+                GrazeMeter::default(),
+                Interpolated::new(&Transform::from_translation(spawn)),
+                Faction::Player,
+                Collider::new(
+                    ColliderShape::Circle {
+                        radius: PLAYER_HITBOX_RADIUS,
+                    },
+                    Layers::PLAYER,
+                    Layers::ENEMY
+                        | Layers::ENEMY_BULLET
+                        | Layers::NEUTRAL_BULLET
+                        | Layers::PICKUP
+                        | Layers::WALL,
+                ),
+                Velocity(Vec3::ZERO),
+                Friction(PLAYER_FRICTION),
+            ))
+            .insert((Dash::default(), aim_mode, devices))
+            .insert(InputManagerBundle::with_map(stick_input_map))
+            .id();
+        bindings.insert_input_maps(&mut commands.entity(entity), devices);
+
+        add_health_bar(&mut commands, entity, 70.);
+        spawn_loadout(&mut commands, &asset_server, entity);
+    }
+}
 
-    add_health_bar(&mut commands, player, 70.);
+/// Hands every player over to a replay or the bot, which press their actions themselves.
+///
+/// Without input maps, leafwing leaves the action states alone and the cursor isn't read.
+pub fn remove_input_maps(mut commands: Commands, players: Query<Entity, With<Player>>) {
+    for player in players.iter() {
+        commands.entity(player).remove::<(
+            InputMap<MoveAction>,
+            InputMap<FireAction>,
+            InputMap<WeaponAction>,
+            InputMap<SpecialAction>,
+            InputMap<StickAction>,
+        )>();
+    }
 }
 
+/// Players start side by side around `PLAYER_SPAWN`, and respawn where they started.
+fn spawn_position(index: usize, count: usize) -> Vec3 {
+    let offset = index as f32 - (count - 1) as f32 / 2.;
+    PLAYER_SPAWN + Vec3::X * offset * PLAYER_SPACING
+}
+
+pub const PLAYER_RADIUS: f32 = 50.;
+/// Only this small core of the player can be hit, the rest of the mesh is for show.
+pub const PLAYER_HITBOX_RADIUS: f32 = 8.;
 const PLAYER_MAX_SPEED: f32 = 1000.;
-const PLAYER_SPEED: f32 = 1200.;
+/// Per second, for each held direction. Reaches `PLAYER_MAX_SPEED` within one step at 64 Hz.
+const PLAYER_ACCELERATION: f32 = 76800.;
 const PLAYER_FRICTION: f32 = 10000.;
 
-fn move_player(mut player: Query<(&mut Velocity, &ActionState<MoveAction>), With<Player>>) {
-    let Ok((mut velocity, action)) = player.get_single_mut() else {
-        return;
-    };
+/// Sticks pushed less than this are considered centered.
+const STICK_DEADZONE: f32 = 0.15;
+/// How far ahead of the player the right stick aims.
+const STICK_AIM_DISTANCE: f32 = 300.;
 
-    for action in action.get_pressed() {
-        velocity.add_velocity_clamped(
-            match action {
-                MoveAction::Left => Vec3::ZERO.with_x(-1. * PLAYER_SPEED),
-                MoveAction::Right => Vec3::ZERO.with_x(1. * PLAYER_SPEED),
-                MoveAction::Up => Vec3::ZERO.with_y(1. * PLAYER_SPEED),
-                MoveAction::Down => Vec3::ZERO.with_y(-1. * PLAYER_SPEED),
+pub const PLAYER_LIVES: u32 = 3;
+const PLAYER_SPAWN: Vec3 = Vec3::ZERO;
+/// Distance between the spawns of the players.
+const PLAYER_SPACING: f32 = 300.;
+/// Seconds between dying and respawning.
+const PLAYER_RESPAWN_DELAY: f32 = 1.5;
+/// Seconds of invulnerability after respawning.
+pub const PLAYER_INVULNERABILITY: f32 = 2.;
+const PLAYER_BLINK_INTERVAL: f32 = 0.1;
+
+fn spawn_lives_text(mut commands: Commands, players: Query<(Entity, &Player)>) {
+    for (entity, player) in players.iter() {
+        commands.spawn((
+            LivesText,
+            PlayerHud(entity),
+            StateScoped(InGame),
+            TextBundle::from_section(
+                "",
+                TextStyle {
+                    font_size: 32.,
+                    color: player.color(),
+                    ..Default::default()
+                },
+            )
+            .with_style(Style {
+                position_type: PositionType::Absolute,
+                right: Val::Px(20.),
+                top: Val::Px(20. + HUD_ROW * (2 * player.0) as f32),
+                ..Default::default()
+            }),
+        ));
+    }
+}
+
+fn update_lives_text(
+    players: Query<&Lives, Changed<Lives>>,
+    mut texts: Query<(&mut Text, &PlayerHud), With<LivesText>>,
+) {
+    for (mut text, hud) in texts.iter_mut() {
+        let Ok(lives) = players.get(hud.0) else {
+            continue;
+        };
+
+        text.sections[0].value = format!("Lives: {}", lives.0);
+    }
+}
+
+pub fn kill_player(
+    mut commands: Commands,
+    mut player: Query<
+        (Entity, &Transform, &Health, &mut Lives, &mut Visibility),
+        (With<Player>, Without<Dead>),
+    >,
+    mut writer: EventWriter<PlayerDied>,
+) {
+    for (entity, transform, health, mut lives, mut visibility) in player.iter_mut() {
+        if health.current > 0.01 {
+            continue;
+        }
+
+        lives.0 = lives.0.saturating_sub(1);
+        *visibility = Visibility::Hidden;
+        commands
+            .entity(entity)
+            .remove::<Invulnerable>()
+            .insert(Dead {
+                timer: Timer::from_seconds(PLAYER_RESPAWN_DELAY, TimerMode::Once),
+                revive: 0.,
+            });
+
+        writer.send(PlayerDied {
+            player: entity,
+            position: transform.translation,
+            lives: lives.0,
+        });
+    }
+}
+
+pub fn respawn_player(
+    mut commands: Commands,
+    mut player: Query<(
+        Entity,
+        &Player,
+        &mut Dead,
+        &Lives,
+        &mut Health,
+        &mut Transform,
+        &mut Velocity,
+        &mut Visibility,
+    )>,
+    count: Res<PlayerCount>,
+    time: Res<Time>,
+    mut writer: EventWriter<PlayerRespawned>,
+) {
+    for (
+        entity,
+        player,
+        mut dead,
+        lives,
+        mut health,
+        mut transform,
+        mut velocity,
+        mut visibility,
+    ) in player.iter_mut()
+    {
+        // Out of lives, the player stays dead until revived or the game over screen takes over.
+        if !dead.timer.tick(time.delta()).just_finished() || lives.0 == 0 {
+            continue;
+        }
+
+        let spawn = spawn_position(player.0, count.0);
+        health.current = health.max;
+        transform.translation = spawn;
+        velocity.0 = Vec3::ZERO;
+        *visibility = Visibility::Inherited;
+        commands.entity(entity).remove::<Dead>().insert((
+            Invulnerable::from_seconds(PLAYER_INVULNERABILITY),
+            Interpolated::new(&transform),
+        ));
+
+        writer.send(PlayerRespawned {
+            player: entity,
+            position: spawn,
+        });
+    }
+}
+
+fn blink_invulnerable(
+    mut commands: Commands,
+    mut player: Query<(Entity, &mut Invulnerable, &mut Visibility), With<Player>>,
+    time: Res<Time>,
+) {
+    for (entity, mut invulnerable, mut visibility) in player.iter_mut() {
+        if invulnerable.timer.tick(time.delta()).finished() {
+            *visibility = Visibility::Inherited;
+            commands.entity(entity).remove::<Invulnerable>();
+            continue;
+        }
+
+        if invulnerable
+            .blink
+            .tick(time.delta())
+            .times_finished_this_tick()
+            % 2
+            == 1
+        {
+            *visibility = match *visibility {
+                Visibility::Hidden => Visibility::Inherited,
+                _ => Visibility::Hidden,
+            };
+        }
+    }
+}
+
+/// Flashes in the player's color where they died or came back.
+#[derive(Component)]
+struct Burst {
+    timer: Timer,
+}
+
+const BURST_DURATION: f32 = 0.5;
+
+fn spawn_bursts(
+    mut commands: Commands,
+    mut died: EventReader<PlayerDied>,
+    mut respawned: EventReader<PlayerRespawned>,
+    players: Query<&Player>,
+    mut player_camera: ResMut<PlayerCamera>,
+) {
+    let mut spawn = |player: Entity, position: Vec3| {
+        commands.spawn((
+            Burst {
+                timer: Timer::from_seconds(BURST_DURATION, TimerMode::Once),
             },
-            PLAYER_MAX_SPEED,
-        );
+            StateScoped(InGame),
+            SpriteBundle {
+                sprite: Sprite {
+                    color: players.get(player).map_or(Color::WHITE, Player::color),
+                    custom_size: Some(Vec2::splat(PLAYER_RADIUS)),
+                    ..Default::default()
+                },
+                transform: Transform::from_translation(position.with_z(2.)),
+                ..Default::default()
+            },
+        ));
+    };
+
+    for died in died.read() {
+        // Losing the last life hits harder.
+        let trauma = if died.lives == 0 { 1. } else { 0.6 };
+        player_camera.push_screen_shake(ScreenShake::new(trauma));
+        spawn(died.player, died.position);
     }
 
-    // if action.get_pressed().is_empty() {
-    //     velocity.0 = Vec3::ZERO;
-    // }
+    for respawned in respawned.read() {
+        spawn(respawned.player, respawned.position);
+    }
 }
 
-fn fire_bullets(
+fn update_bursts(
     mut commands: Commands,
-    mut player: Query<(&Transform, &ActionState<FireAction>, &mut Velocity), With<Player>>,
+    mut bursts: Query<(Entity, &mut Burst, &mut Sprite, &mut Transform)>,
+    time: Res<Time>,
+) {
+    for (entity, mut burst, mut sprite, mut transform) in bursts.iter_mut() {
+        if burst.timer.tick(time.delta()).finished() {
+            commands.entity(entity).despawn();
+            continue;
+        }
+
+        let t = burst.timer.fraction();
+        sprite.color.set_alpha(1. - t);
+        transform.scale = Vec3::splat(1. + 3. * t);
+    }
+}
+
+/// Runs every frame after the input is updated, whether by leafwing, a replay or the bot.
+pub fn buffer_input(
+    mut player: Query<
+        (
+            &ActionState<MoveAction>,
+            &ActionState<FireAction>,
+            &ActionState<WeaponAction>,
+            &ActionState<SpecialAction>,
+            &ActionState<StickAction>,
+            &mut BufferedInput,
+            Has<Dead>,
+        ),
+        With<Player>,
+    >,
+) {
+    for (moves, fire, weapon, special, sticks, mut input, dead) in player.iter_mut() {
+        // Nothing pressed while dead carries over to the respawn.
+        if dead {
+            *input = BufferedInput::default();
+            continue;
+        }
+
+        let stick = sticks.clamped_axis_pair(&StickAction::Move);
+        let stick = if stick.length() < STICK_DEADZONE {
+            Vec2::ZERO
+        } else {
+            stick
+        };
+
+        let keys: Vec2 = moves
+            .get_pressed()
+            .iter()
+            .filter_map(|action| match action {
+                MoveAction::Left => Some(Vec2::NEG_X),
+                MoveAction::Right => Some(Vec2::X),
+                MoveAction::Up => Some(Vec2::Y),
+                MoveAction::Down => Some(Vec2::NEG_Y),
+                MoveAction::Focus | MoveAction::Dash => None,
+            })
+            .sum();
+        input.movement = keys + stick;
+        input.focus = moves.pressed(&MoveAction::Focus);
+        input.dash |= moves.just_pressed(&MoveAction::Dash);
+        input.fire |= fire.just_pressed(&FireAction);
+        input.firing = fire.pressed(&FireAction);
+        input.cycle += weapon.just_pressed(&WeaponAction::Next) as i32
+            - weapon.just_pressed(&WeaponAction::Previous) as i32;
+        input.special |= special.just_pressed(&SpecialAction);
+    }
+}
+
+/// Stops players at the edge of the `ARENA`.
+fn keep_players_in_arena(mut players: Query<(&mut Transform, &mut Velocity), With<Player>>) {
+    for (mut transform, mut velocity) in players.iter_mut() {
+        let position = transform.translation.truncate();
+        let clamped = position.clamp(ARENA.min, ARENA.max);
+        if clamped == position {
+            continue;
+        }
+
+        transform.translation = clamped.extend(transform.translation.z);
+        if clamped.x != position.x {
+            velocity.0.x = 0.;
+        }
+        if clamped.y != position.y {
+            velocity.0.y = 0.;
+        }
+    }
+}
+
+/// Dashing players are moved by their `Dash` instead.
+pub fn move_player(
+    mut player: Query<(&mut Velocity, &BufferedInput, &Dash), (With<Player>, Without<Dead>)>,
+    time: Res<Time>,
+) {
+    let dt = time.delta_seconds();
+    for (mut velocity, input, dash) in player.iter_mut() {
+        if dash.active() {
+            continue;
+        }
+
+        let (acceleration, max_speed) = if input.focus {
+            (FOCUS_ACCELERATION, FOCUS_MAX_SPEED)
+        } else {
+            (PLAYER_ACCELERATION, PLAYER_MAX_SPEED)
+        };
+
+        // Only a partly pushed stick is shorter than 1, held keys always reach full speed.
+        let throttle = if input.movement == Vec2::ZERO {
+            1.
+        } else {
+            input.movement.length().min(1.)
+        };
+
+        velocity.add_velocity_clamped(
+            input.movement.extend(0.) * acceleration * dt,
+            max_speed * throttle,
+        );
+    }
+}
+
+/// Only players with an `InputMap` aim with the cursor or the right stick.
+///
+/// Pushing the right stick switches to gamepad aiming, moving the mouse or clicking switches back
+/// for players that use the mouse.
+pub fn update_aim(
+    mut player: Query<
+        (
+            &mut Aim,
+            &mut AimMode,
+            &Transform,
+            &ActionState<StickAction>,
+            &Devices,
+        ),
+        (With<Player>, With<InputMap<FireAction>>),
+    >,
     q_window: Query<&Window, With<PrimaryWindow>>,
     q_camera: Query<(&Camera, &GlobalTransform), With<MainCamera>>,
-    mut writer: EventWriter<SpawnBullet>,
+    mut cursor_moved: EventReader<CursorMoved>,
+    mouse_buttons: Res<ButtonInput<MouseButton>>,
 ) {
-    let Ok((player_transform, action, mut velocity)) = player.get_single_mut() else {
+    let (Ok(window), Ok((camera, camera_transform))) =
+        (q_window.get_single(), q_camera.get_single())
+    else {
         return;
     };
 
-    let (camera, camera_transform) = q_camera.single();
-    let window = q_window.single();
-
-    if let Some(world_position) = window
+    let cursor = window
         .cursor_position()
-        .and_then(|cursor| camera.viewport_to_world(camera_transform, cursor))
-        .map(|ray| ray.origin.truncate())
+        .and_then(|cursor| camera.viewport_to_world_2d(camera_transform, cursor));
+    let mouse_used =
+        cursor_moved.read().count() > 0 || mouse_buttons.get_just_pressed().next().is_some();
+
+    for (mut aim, mut mode, transform, sticks, devices) in player.iter_mut() {
+        let stick = sticks.axis_pair(&StickAction::Aim);
+        if stick.length() >= STICK_DEADZONE {
+            *mode = AimMode::Gamepad(stick.normalize());
+        } else if mouse_used && devices.keyboard_mouse() {
+            *mode = AimMode::Mouse;
+        }
+
+        aim.0 = match *mode {
+            AimMode::Mouse => cursor,
+            AimMode::Gamepad(direction) => {
+                Some(transform.translation.truncate() + direction * STICK_AIM_DISTANCE)
+            }
+        };
+    }
+}
+
+fn fire_bullets(
+    mut player: Query<
+        (
+            Entity,
+            &Transform,
+            &mut BufferedInput,
+            &Aim,
+            &mut Velocity,
+            &WeaponInventory,
+            &Modifiers,
+        ),
+        (With<Player>, Without<Dead>),
+    >,
+    mut weapons: Query<&mut Weapon>,
+    mut writer: EventWriter<SpawnBullet>,
+    mut player_camera: ResMut<PlayerCamera>,
+) {
+    for (entity, player_transform, mut input, aim, mut velocity, inventory, modifiers) in
+        player.iter_mut()
     {
-        if action.just_pressed(&FireAction) {
-            let mut bullet_velocity =
-                Vec3::new(world_position.x, world_position.y, 0.) - player_transform.translation;
+        // A press is used up even when the weapon can't fire, so it isn't delayed until it can.
+        let pressed = std::mem::take(&mut input.fire);
+        let (Some(world_position), Some(mut weapon)) = (
+            aim.0,
+            inventory
+                .current()
+                .and_then(|weapon| weapons.get_mut(weapon).ok()),
+        ) else {
+            continue;
+        };
+
+        let triggered = pressed || (weapon.automatic && input.firing);
+        if !triggered || !weapon.ready() {
+            continue;
+        }
+
+        let Some(direction) =
+            (world_position - player_transform.translation.truncate()).try_normalize()
+        else {
+            continue;
+        };
 
-            velocity.0 -= bullet_velocity.normalize_or_zero() * 1000.;
+        weapon.fire(modifiers.fire_rate());
+        velocity.0 -= direction.extend(0.) * weapon.recoil;
+        player_camera.push_screen_shake(ScreenShake::new(0.1).with_kick(-direction, 10.));
 
+        let spread = if input.focus { FOCUS_SPREAD } else { 1. };
+        for bullet_direction in weapon.directions(direction, modifiers.extra_projectiles(), spread)
+        {
             writer.send(SpawnBullet {
-                ty: BulletType::Ball,
+                ty: weapon.ty.clone(),
+                faction: Faction::Player,
+                owner: Some(entity),
                 position: player_transform.translation,
-                direction: bullet_velocity,
+                direction: bullet_direction.extend(0.),
             });
         }
     }
@@ -0,0 +1,669 @@
+use crate::{
+    graze::SpecialAction,
+    options::Options,
+    player::{
+        buffer_input, remove_input_maps, spawn_players, update_aim, Aim, FireAction, Lives,
+        MoveAction, Player, PlayerCount, StickAction, MAX_PLAYERS,
+    },
+    rng::{reseed_rng, GameRng},
+    score::Scores,
+    state::{AppState, InGame, RequiredAssets},
+    weapon::WeaponAction,
+    Health,
+};
+use bevy::{prelude::*, time::TimeUpdateStrategy};
+use leafwing_input_manager::{plugin::InputManagerSystem, prelude::*};
+use std::{
+    fs,
+    io::{self, Read, Write},
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+/// Records every run with `--record <path>`, or plays one back with `--replay <path>`.
+///
+/// A replay stores the seed and player count of the run and, for every frame, the virtual time
+/// step and the input of every player. Playing it back feeds both into the app, so the run is
+/// simulated exactly as it was recorded, then prints how it ended and exits.
+pub struct ReplayPlugin;
+
+impl Plugin for ReplayPlugin {
+    fn build(&self, app: &mut App) {
+        let options = app.world().resource::<Options>().clone();
+
+        if let Some(path) = options.record {
+            app.insert_resource(Recorder { path, replay: None })
+                .add_systems(OnEnter(InGame), start_recording.after(reseed_rng))
+                .add_systems(PostUpdate, record_frame.run_if(in_state(AppState::Playing)))
+                .add_systems(OnExit(InGame), save_recording)
+                .add_systems(Last, save_recording.run_if(on_event::<AppExit>()));
+        }
+
+        if let Some(path) = options.replay {
+            let replay = match Replay::load(&path) {
+                Ok(replay) => replay,
+                Err(err) => {
+                    error!("Could not load replay `{}`: {err}", path.display());
+                    return;
+                }
+            };
+
+            info!(
+                "Replaying `{}`: {} frames of {} players with seed {}",
+                path.display(),
+                replay.frames.len(),
+                replay.players,
+                replay.seed
+            );
+            app.insert_resource(GameRng::new(Some(replay.seed)))
+                .insert_resource(Playback {
+                    replay,
+                    next: 0,
+                    started: false,
+                })
+                .add_systems(Update, start_replay.run_if(in_state(AppState::Title)))
+                .add_systems(OnEnter(InGame), remove_input_maps.after(spawn_players))
+                .add_systems(
+                    PreUpdate,
+                    play_frame
+                        .after(InputManagerSystem::ManualControl)
+                        .after(update_aim)
+                        .before(buffer_input),
+                )
+                .add_systems(
+                    Last,
+                    (step_replay_time, finish_replay)
+                        .chain()
+                        .before(save_recording),
+                );
+        }
+    }
+}
+
+/// Input of every player for a single frame.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct ReplayFrame {
+    /// Virtual time that passed during the frame.
+    pub delta: Duration,
+    /// Indexed like `Player`, only the first `Replay::players` are used.
+    pub players: [PlayerInput; MAX_PLAYERS],
+}
+
+/// Input of a single player for a single frame.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct PlayerInput {
+    pub moves: [bool; 4],
+    pub fire: bool,
+    pub weapons: [bool; 2],
+    pub special: bool,
+    pub focus: bool,
+    pub dash: bool,
+    /// Left stick, before the deadzone is applied.
+    pub stick: Vec2,
+    pub aim: Option<Vec2>,
+}
+
+const MOVES: [MoveAction; 4] = [
+    MoveAction::Left,
+    MoveAction::Right,
+    MoveAction::Up,
+    MoveAction::Down,
+];
+
+const WEAPONS: [WeaponAction; 2] = [WeaponAction::Previous, WeaponAction::Next];
+
+/// Stored as the magic bytes, a version byte, the seed, the player count, the frame count, and
+/// then every frame as its delta in nanoseconds followed by the input of every player: two bytes
+/// of flags, the aim position when there is one, and the left stick when it is pushed.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Replay {
+    pub seed: u64,
+    pub players: usize,
+    pub frames: Vec<ReplayFrame>,
+}
+
+const MAGIC: &[u8; 4] = b"HNRP";
+const VERSION: u8 = 7;
+
+const FLAG_FIRE: u16 = 1 << 4;
+const FLAG_AIM: u16 = 1 << 5;
+/// Index of the first of the bits, one per weapon action, that follow `FLAG_AIM`.
+const WEAPON_FLAG_SHIFT: u16 = 6;
+const FLAG_SPECIAL: u16 = 1 << 8;
+const FLAG_FOCUS: u16 = 1 << 9;
+const FLAG_DASH: u16 = 1 << 10;
+const FLAG_STICK: u16 = 1 << 11;
+
+impl Replay {
+    pub fn load(path: &Path) -> io::Result<Self> {
+        Self::read(&mut io::BufReader::new(fs::File::open(path)?))
+    }
+
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        let mut writer = io::BufWriter::new(fs::File::create(path)?);
+        self.write(&mut writer)?;
+        writer.flush()
+    }
+
+    pub fn write(&self, writer: &mut impl Write) -> io::Result<()> {
+        writer.write_all(MAGIC)?;
+        writer.write_all(&[VERSION])?;
+        writer.write_all(&self.seed.to_le_bytes())?;
+        writer.write_all(&[self.players as u8])?;
+        writer.write_all(&(self.frames.len() as u32).to_le_bytes())?;
+
+        for frame in self.frames.iter() {
+            // Frame times are clamped by `Time<Virtual>`, so they always fit.
+            let delta = frame.delta.as_nanos().min(u32::MAX as u128) as u32;
+            writer.write_all(&delta.to_le_bytes())?;
+
+            for input in frame.players.iter().take(self.players) {
+                input.write(writer)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn read(reader: &mut impl Read) -> io::Result<Self> {
+        let mut magic = [0; 4];
+        reader.read_exact(&mut magic)?;
+        let [version] = read_bytes(reader)?;
+        if &magic != MAGIC || version != VERSION {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "not a replay, or from an incompatible version",
+            ));
+        }
+
+        let seed = u64::from_le_bytes(read_bytes(reader)?);
+        let [players] = read_bytes(reader)?;
+        let players = players as usize;
+        if !(1..=MAX_PLAYERS).contains(&players) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("replay of {players} players, expected 1 to {MAX_PLAYERS}"),
+            ));
+        }
+        let count = u32::from_le_bytes(read_bytes(reader)?);
+
+        // Not preallocated from `count`, which a corrupt file could set to anything.
+        let mut frames = Vec::new();
+        for _ in 0..count {
+            let delta = Duration::from_nanos(u32::from_le_bytes(read_bytes(reader)?) as u64);
+            let mut frame = ReplayFrame {
+                delta,
+                ..Default::default()
+            };
+            for input in frame.players.iter_mut().take(players) {
+                *input = PlayerInput::read(reader)?;
+            }
+            frames.push(frame);
+        }
+
+        Ok(Self {
+            seed,
+            players,
+            frames,
+        })
+    }
+}
+
+impl PlayerInput {
+    fn write(&self, writer: &mut impl Write) -> io::Result<()> {
+        let mut flags = 0;
+        for (i, pressed) in self.moves.iter().enumerate() {
+            if *pressed {
+                flags |= 1 << i;
+            }
+        }
+        if self.fire {
+            flags |= FLAG_FIRE;
+        }
+        if self.aim.is_some() {
+            flags |= FLAG_AIM;
+        }
+        for (i, pressed) in self.weapons.iter().enumerate() {
+            if *pressed {
+                flags |= 1 << (WEAPON_FLAG_SHIFT + i as u16);
+            }
+        }
+        if self.special {
+            flags |= FLAG_SPECIAL;
+        }
+        if self.focus {
+            flags |= FLAG_FOCUS;
+        }
+        if self.dash {
+            flags |= FLAG_DASH;
+        }
+        if self.stick != Vec2::ZERO {
+            flags |= FLAG_STICK;
+        }
+        writer.write_all(&flags.to_le_bytes())?;
+
+        if let Some(aim) = self.aim {
+            writer.write_all(&aim.x.to_le_bytes())?;
+            writer.write_all(&aim.y.to_le_bytes())?;
+        }
+        if self.stick != Vec2::ZERO {
+            writer.write_all(&self.stick.x.to_le_bytes())?;
+            writer.write_all(&self.stick.y.to_le_bytes())?;
+        }
+
+        Ok(())
+    }
+
+    fn read(reader: &mut impl Read) -> io::Result<Self> {
+        let flags = u16::from_le_bytes(read_bytes(reader)?);
+        let aim = if flags & FLAG_AIM != 0 {
+            let x = f32::from_le_bytes(read_bytes(reader)?);
+            let y = f32::from_le_bytes(read_bytes(reader)?);
+            Some(Vec2::new(x, y))
+        } else {
+            None
+        };
+        let stick = if flags & FLAG_STICK != 0 {
+            let x = f32::from_le_bytes(read_bytes(reader)?);
+            let y = f32::from_le_bytes(read_bytes(reader)?);
+            Vec2::new(x, y)
+        } else {
+            Vec2::ZERO
+        };
+
+        Ok(Self {
+            moves: std::array::from_fn(|i| flags & (1 << i) != 0),
+            fire: flags & FLAG_FIRE != 0,
+            weapons: std::array::from_fn(|i| flags & (1 << (WEAPON_FLAG_SHIFT + i as u16)) != 0),
+            special: flags & FLAG_SPECIAL != 0,
+            focus: flags & FLAG_FOCUS != 0,
+            dash: flags & FLAG_DASH != 0,
+            stick,
+            aim,
+        })
+    }
+}
+
+fn read_bytes<const N: usize>(reader: &mut impl Read) -> io::Result<[u8; N]> {
+    let mut bytes = [0; N];
+    reader.read_exact(&mut bytes)?;
+    Ok(bytes)
+}
+
+/// Records the current run, written to `path` when it ends or the app exits.
+#[derive(Resource)]
+pub struct Recorder {
+    path: PathBuf,
+    replay: Option<Replay>,
+}
+
+fn start_recording(
+    mut recorder: ResMut<Recorder>,
+    rng: Res<GameRng>,
+    player_count: Res<PlayerCount>,
+) {
+    recorder.replay = Some(Replay {
+        seed: rng.seed,
+        players: player_count.0,
+        frames: Vec::new(),
+    });
+}
+
+/// Runs after everything else in the frame, when the input is exactly what the simulation saw.
+fn record_frame(
+    mut recorder: ResMut<Recorder>,
+    players: Query<
+        (
+            &Player,
+            &ActionState<MoveAction>,
+            &ActionState<FireAction>,
+            &ActionState<WeaponAction>,
+            &ActionState<SpecialAction>,
+            &ActionState<StickAction>,
+            &Aim,
+        ),
+        With<Player>,
+    >,
+    time: Res<Time<Virtual>>,
+) {
+    let Some(replay) = recorder.replay.as_mut() else {
+        return;
+    };
+
+    let mut frame = ReplayFrame {
+        delta: time.delta(),
+        ..Default::default()
+    };
+    for (player, moves, fire, weapons, special, sticks, aim) in players.iter() {
+        let Some(input) = frame.players.get_mut(player.0) else {
+            continue;
+        };
+
+        *input = PlayerInput {
+            moves: MOVES.map(|action| moves.pressed(&action)),
+            fire: fire.pressed(&FireAction),
+            weapons: WEAPONS.map(|action| weapons.pressed(&action)),
+            special: special.pressed(&SpecialAction),
+            focus: moves.pressed(&MoveAction::Focus),
+            dash: moves.pressed(&MoveAction::Dash),
+            stick: sticks.axis_pair(&StickAction::Move),
+            aim: aim.0,
+        };
+    }
+
+    replay.frames.push(frame);
+}
+
+pub fn save_recording(mut recorder: ResMut<Recorder>) {
+    let Some(replay) = recorder.replay.take() else {
+        return;
+    };
+
+    match replay.save(&recorder.path) {
+        Ok(()) => info!(
+            "Recorded {} frames with seed {} to `{}`",
+            replay.frames.len(),
+            replay.seed,
+            recorder.path.display()
+        ),
+        Err(err) => error!(
+            "Could not save recording to `{}`: {err}",
+            recorder.path.display()
+        ),
+    }
+}
+
+/// Plays back a replay, one recorded frame per app frame.
+#[derive(Resource)]
+struct Playback {
+    replay: Replay,
+    next: usize,
+    started: bool,
+}
+
+/// Starts the run as soon as it can start the same way it did when recorded.
+fn start_replay(
+    mut playback: ResMut<Playback>,
+    required: Res<RequiredAssets>,
+    asset_server: Res<AssetServer>,
+    mut strategy: ResMut<TimeUpdateStrategy>,
+    mut player_count: ResMut<PlayerCount>,
+    mut next_state: ResMut<NextState<AppState>>,
+) {
+    if playback.started || !required.ready(&asset_server) {
+        return;
+    }
+
+    playback.started = true;
+    player_count.0 = playback.replay.players;
+    if let Some(frame) = playback.replay.frames.first() {
+        *strategy = TimeUpdateStrategy::ManualDuration(frame.delta);
+    }
+    next_state.set(AppState::Playing);
+}
+
+/// Replaces the input of every player with the recorded one, once leafwing is done updating it.
+fn play_frame(
+    mut playback: ResMut<Playback>,
+    mut players: Query<
+        (
+            &Player,
+            &mut ActionState<MoveAction>,
+            &mut ActionState<FireAction>,
+            &mut ActionState<WeaponAction>,
+            &mut ActionState<SpecialAction>,
+            &mut ActionState<StickAction>,
+            &mut Aim,
+        ),
+        With<Player>,
+    >,
+) {
+    if !playback.started {
+        return;
+    }
+
+    let Some(frame) = playback.replay.frames.get(playback.next).copied() else {
+        return;
+    };
+    playback.next += 1;
+
+    for (player, mut moves, mut fire, mut weapons, mut special, mut sticks, mut aim) in
+        players.iter_mut()
+    {
+        let Some(input) = frame.players.get(player.0) else {
+            continue;
+        };
+
+        for (action, pressed) in MOVES.iter().zip(input.moves) {
+            if pressed {
+                moves.press(action);
+            } else {
+                moves.release(action);
+            }
+        }
+
+        for (action, pressed) in WEAPONS.iter().zip(input.weapons) {
+            if pressed {
+                weapons.press(action);
+            } else {
+                weapons.release(action);
+            }
+        }
+
+        if input.fire {
+            fire.press(&FireAction);
+        } else {
+            fire.release(&FireAction);
+        }
+
+        if input.special {
+            special.press(&SpecialAction);
+        } else {
+            special.release(&SpecialAction);
+        }
+
+        if input.focus {
+            moves.press(&MoveAction::Focus);
+        } else {
+            moves.release(&MoveAction::Focus);
+        }
+
+        if input.dash {
+            moves.press(&MoveAction::Dash);
+        } else {
+            moves.release(&MoveAction::Dash);
+        }
+
+        sticks.set_axis_pair(&StickAction::Move, input.stick);
+        aim.0 = input.aim;
+    }
+}
+
+/// Sets how much time the next frame simulates, since time is updated before anything else runs.
+fn step_replay_time(playback: Res<Playback>, mut strategy: ResMut<TimeUpdateStrategy>) {
+    if !playback.started {
+        return;
+    }
+
+    if let Some(frame) = playback.replay.frames.get(playback.next) {
+        *strategy = TimeUpdateStrategy::ManualDuration(frame.delta);
+    }
+}
+
+fn finish_replay(
+    playback: Res<Playback>,
+    player: Query<(&Health, &Lives), With<Player>>,
+    state: Res<State<AppState>>,
+    scores: Res<Scores>,
+    mut writer: EventWriter<AppExit>,
+) {
+    if !playback.started {
+        return;
+    }
+
+    if *state.get() == AppState::GameOver {
+        info!(
+            "Replay finished after {} frames: game over, score {}",
+            playback.next,
+            scores.total()
+        );
+    } else if playback.next >= playback.replay.frames.len() {
+        for (health, lives) in player.iter() {
+            info!(
+                "Replay finished after {} frames: score {}, health {}/{}, lives {}",
+                playback.next,
+                scores.total(),
+                health.current,
+                health.max,
+                lives.0
+            );
+        }
+    } else {
+        return;
+    }
+
+    writer.send(AppExit::Success);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::headless::tests::run;
+
+    fn every_flag() -> PlayerInput {
+        PlayerInput {
+            moves: [true; 4],
+            fire: true,
+            weapons: [true; 2],
+            special: true,
+            focus: true,
+            dash: true,
+            stick: Vec2::new(0.5, -0.25),
+            aim: Some(Vec2::new(100., -50.)),
+        }
+    }
+
+    fn frame(players: [PlayerInput; MAX_PLAYERS]) -> ReplayFrame {
+        ReplayFrame {
+            delta: Duration::from_secs_f64(1. / 60.),
+            players,
+        }
+    }
+
+    fn round_trip(replay: &Replay) -> io::Result<Replay> {
+        let mut bytes = Vec::new();
+        replay.write(&mut bytes)?;
+        Replay::read(&mut bytes.as_slice())
+    }
+
+    #[test]
+    fn round_trips() {
+        let aim_only = PlayerInput {
+            aim: Some(Vec2::new(-3., 7.5)),
+            ..Default::default()
+        };
+        let stick_only = PlayerInput {
+            stick: Vec2::new(-1., 0.),
+            ..Default::default()
+        };
+        let single = PlayerInput {
+            moves: [false, true, false, false],
+            weapons: [false, true],
+            dash: true,
+            ..Default::default()
+        };
+
+        let solo = Replay {
+            seed: 42,
+            players: 1,
+            frames: vec![
+                frame([every_flag(), PlayerInput::default()]),
+                frame([PlayerInput::default(); MAX_PLAYERS]),
+                frame([aim_only, PlayerInput::default()]),
+                frame([stick_only, PlayerInput::default()]),
+                frame([single, PlayerInput::default()]),
+            ],
+        };
+        assert_eq!(round_trip(&solo).unwrap(), solo);
+
+        let coop = Replay {
+            seed: u64::MAX,
+            players: 2,
+            frames: vec![
+                frame([every_flag(), every_flag()]),
+                frame([PlayerInput::default(), every_flag()]),
+                frame([aim_only, stick_only]),
+                frame([PlayerInput::default(); MAX_PLAYERS]),
+            ],
+        };
+        assert_eq!(round_trip(&coop).unwrap(), coop);
+
+        let empty = Replay {
+            seed: 0,
+            players: 1,
+            frames: Vec::new(),
+        };
+        assert_eq!(round_trip(&empty).unwrap(), empty);
+    }
+
+    fn header(magic: &[u8; 4], version: u8, players: u8) -> Vec<u8> {
+        let mut bytes = magic.to_vec();
+        bytes.push(version);
+        bytes.extend(7u64.to_le_bytes());
+        bytes.push(players);
+        bytes.extend(0u32.to_le_bytes());
+        bytes
+    }
+
+    #[test]
+    fn rejects_invalid_headers() {
+        let read = |bytes: Vec<u8>| Replay::read(&mut bytes.as_slice()).map_err(|err| err.kind());
+
+        assert!(read(header(MAGIC, VERSION, 1)).is_ok());
+        assert_eq!(
+            read(header(b"NOPE", VERSION, 1)),
+            Err(io::ErrorKind::InvalidData)
+        );
+        assert_eq!(
+            read(header(MAGIC, VERSION - 1, 1)),
+            Err(io::ErrorKind::InvalidData)
+        );
+        assert_eq!(
+            read(header(MAGIC, VERSION, 0)),
+            Err(io::ErrorKind::InvalidData)
+        );
+        assert_eq!(
+            read(header(MAGIC, VERSION, MAX_PLAYERS as u8 + 1)),
+            Err(io::ErrorKind::InvalidData)
+        );
+        assert_eq!(
+            read(header(MAGIC, VERSION, 1)[..10].to_vec()),
+            Err(io::ErrorKind::UnexpectedEof)
+        );
+    }
+
+    /// A bot run played back from its recording ends the same way.
+    #[test]
+    fn replay_reproduces_recorded_run() {
+        let path = std::env::temp_dir().join(format!("hypernova-{}.hnrp", std::process::id()));
+
+        let recorded = run(Options {
+            seed: Some(3),
+            frames: Some(60 * 30),
+            record: Some(path.clone()),
+            ..Default::default()
+        });
+        let replayed = run(Options {
+            replay: Some(path.clone()),
+            ..Default::default()
+        });
+        fs::remove_file(&path).unwrap();
+
+        assert!(!recorded.game_over, "the run ended in a game over");
+        assert_eq!(replayed, recorded);
+    }
+}
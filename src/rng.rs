@@ -0,0 +1,86 @@
+use crate::{options::Options, state::InGame};
+use bevy::prelude::*;
+use rand::SeedableRng;
+use rand_chacha::ChaCha8Rng;
+
+pub struct RngPlugin;
+
+impl Plugin for RngPlugin {
+    fn build(&self, app: &mut App) {
+        let seed = app.world().resource::<Options>().seed;
+        app.insert_resource(GameRng::new(seed))
+            .add_systems(OnEnter(InGame), (reseed_rng, restart_fixed_time));
+    }
+}
+
+/// Every random decision in the game goes through here, so that a run can be replayed from its seed.
+///
+/// Pass `--seed <u64>` on the command line to play the same seed every run, otherwise each run
+/// picks a new one. Both streams are ChaCha8, whose output is fixed for a given seed on every
+/// platform and rand version, unlike `StdRng`, so seeds and replays stay valid across updates.
+#[derive(Resource)]
+pub struct GameRng {
+    /// Seed of the current run.
+    pub seed: u64,
+    fixed_seed: Option<u64>,
+    /// Anything that affects the simulation: spawns, enemy behaviors, drops.
+    pub gameplay: ChaCha8Rng,
+    /// Visual effects only, so that adding or removing one never changes the outcome of a run.
+    pub cosmetic: ChaCha8Rng,
+}
+
+/// Mixed into the seed of the cosmetic stream, so that it doesn't mirror the gameplay one.
+const COSMETIC_STREAM: u64 = 0x9e37_79b9_7f4a_7c15;
+
+impl GameRng {
+    pub fn new(fixed_seed: Option<u64>) -> Self {
+        let seed = fixed_seed.unwrap_or_else(rand::random);
+        Self {
+            seed,
+            fixed_seed,
+            gameplay: ChaCha8Rng::seed_from_u64(seed),
+            cosmetic: ChaCha8Rng::seed_from_u64(seed ^ COSMETIC_STREAM),
+        }
+    }
+
+    /// Restarts both streams, from the fixed seed if there is one.
+    pub fn reseed(&mut self) {
+        *self = Self::new(self.fixed_seed);
+    }
+}
+
+pub fn reseed_rng(mut rng: ResMut<GameRng>) {
+    rng.reseed();
+    info!("Starting run with seed {}", rng.seed);
+}
+
+/// Starts every run on a fixed step boundary, otherwise how long the title screen was up would
+/// change which fixed steps see which frame's input.
+fn restart_fixed_time(mut time: ResMut<Time<Fixed>>) {
+    let overstep = time.overstep();
+    time.discard_overstep(overstep);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::RngCore;
+
+    /// Recorded seeds must keep producing the same runs, so the streams may never change.
+    #[test]
+    fn streams_are_stable() {
+        let mut rng = GameRng::new(Some(42));
+        assert_eq!(rng.gameplay.next_u64(), 0xae90_bfb5_395d_5ba1);
+        assert_eq!(rng.gameplay.next_u64(), 0xf345_3fc6_2579_9188);
+        assert_eq!(rng.cosmetic.next_u64(), 0xd1ce_16d2_8fee_5333);
+    }
+
+    #[test]
+    fn reseed_restarts_fixed_seed() {
+        let mut rng = GameRng::new(Some(7));
+        let first = rng.gameplay.next_u64();
+        rng.reseed();
+        assert_eq!(rng.seed, 7);
+        assert_eq!(rng.gameplay.next_u64(), first);
+    }
+}
@@ -0,0 +1,64 @@
+use bevy::{
+    asset::{io::Reader, AssetLoader, AsyncReadExt, LoadContext},
+    prelude::*,
+};
+use serde::Deserialize;
+use std::marker::PhantomData;
+use thiserror::Error;
+
+/// An asset deserialized as-is from a RON file written by designers.
+pub trait RonAsset: Asset + for<'de> Deserialize<'de> {
+    /// File extensions without the leading dot, e.g. `bullet.ron`.
+    const EXTENSIONS: &'static [&'static str];
+
+    /// Rejects data that parses but can't be used, with a message for the designer.
+    fn validate(&self) -> Result<(), String> {
+        Ok(())
+    }
+}
+
+pub trait RonAssetApp {
+    fn init_ron_asset<A: RonAsset>(&mut self) -> &mut Self;
+}
+
+impl RonAssetApp for App {
+    fn init_ron_asset<A: RonAsset>(&mut self) -> &mut Self {
+        self.init_asset::<A>()
+            .register_asset_loader(RonAssetLoader::<A>(PhantomData))
+    }
+}
+
+struct RonAssetLoader<A>(PhantomData<fn() -> A>);
+
+#[derive(Debug, Error)]
+enum RonAssetLoaderError {
+    #[error("could not read asset: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("could not parse asset: {0}")]
+    Ron(#[from] ron::error::SpannedError),
+    #[error("invalid asset: {0}")]
+    Invalid(String),
+}
+
+impl<A: RonAsset> AssetLoader for RonAssetLoader<A> {
+    type Asset = A;
+    type Settings = ();
+    type Error = RonAssetLoaderError;
+
+    async fn load<'a>(
+        &'a self,
+        reader: &'a mut Reader<'_>,
+        _settings: &'a (),
+        _load_context: &'a mut LoadContext<'_>,
+    ) -> Result<Self::Asset, Self::Error> {
+        let mut bytes = Vec::new();
+        reader.read_to_end(&mut bytes).await?;
+        let asset: A = ron::de::from_bytes(&bytes)?;
+        asset.validate().map_err(RonAssetLoaderError::Invalid)?;
+        Ok(asset)
+    }
+
+    fn extensions(&self) -> &[&str] {
+        A::EXTENSIONS
+    }
+}
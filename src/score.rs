@@ -0,0 +1,296 @@
+use crate::{
+    enemy::EnemyKilled,
+    options::Options,
+    pickup::Modifiers,
+    player::{spawn_players, Player, PlayerCount, PlayerHud, HUD_ROW},
+    state::{spawn_game_over_screen, AppState, InGame, Screen},
+    storage, GameSet,
+};
+use bevy::prelude::*;
+
+pub struct ScorePlugin;
+
+impl Plugin for ScorePlugin {
+    fn build(&self, app: &mut App) {
+        // Runs driven by a bot or a replay don't count.
+        let options = app.world().resource::<Options>();
+        let persist = !options.headless && options.replay.is_none();
+
+        app.init_resource::<Scores>()
+            .insert_resource(HighScores {
+                scores: Vec::new(),
+                persist,
+            })
+            .add_systems(Startup, load_high_scores)
+            .add_systems(
+                OnEnter(InGame),
+                (reset_scores, spawn_score_text.after(spawn_players)),
+            )
+            .add_systems(
+                OnEnter(AppState::GameOver),
+                record_high_score.after(spawn_game_over_screen),
+            )
+            .add_systems(
+                FixedUpdate,
+                (
+                    decay_combo.in_set(GameSet::Control),
+                    award_kills.in_set(GameSet::Death),
+                ),
+            )
+            .add_systems(
+                Update,
+                update_score_text.run_if(in_state(AppState::Playing)),
+            );
+    }
+}
+
+/// Points of every player in the current run, indexed like `Player`.
+#[derive(Resource, Debug, Clone, Default)]
+pub struct Scores(pub Vec<Score>);
+
+impl Scores {
+    /// Points of all players together, what the run counts for in the high scores.
+    pub fn total(&self) -> u64 {
+        self.0.iter().map(|score| score.points).sum()
+    }
+}
+
+/// Points of a player in the current run.
+///
+/// Every kill raises the combo multiplier. It starts decaying `COMBO_GRACE` seconds after the
+/// last kill, back down to 1.
+#[derive(Debug, Clone)]
+pub struct Score {
+    pub points: u64,
+    pub combo: f32,
+    /// Seconds left before the combo starts decaying.
+    grace: f32,
+}
+
+impl Default for Score {
+    fn default() -> Self {
+        Self {
+            points: 0,
+            combo: 1.,
+            grace: 0.,
+        }
+    }
+}
+
+const KILL_POINTS: f32 = 100.;
+const GRAZE_POINTS: f32 = 10.;
+const COMBO_STEP: f32 = 0.1;
+const MAX_COMBO: f32 = 8.;
+const COMBO_GRACE: f32 = 2.;
+/// Combo lost per second once it decays.
+const COMBO_DECAY: f32 = 0.5;
+
+impl Score {
+    /// `bonus` multiplies the points on top of the combo, e.g. from a pickup.
+    pub fn add_kill(&mut self, bonus: f32) {
+        self.add(KILL_POINTS * bonus);
+        self.combo = (self.combo + COMBO_STEP).min(MAX_COMBO);
+        self.grace = COMBO_GRACE;
+    }
+
+    /// Grazes are worth a few points but don't keep the combo going.
+    pub fn add_graze(&mut self, bonus: f32) {
+        self.add(GRAZE_POINTS * bonus);
+    }
+
+    fn add(&mut self, points: f32) {
+        self.points += (points * self.combo).round() as u64;
+    }
+
+    fn decay(&mut self, dt: f32) {
+        if self.grace > 0. {
+            self.grace -= dt;
+        } else {
+            self.combo = (self.combo - COMBO_DECAY * dt).max(1.);
+        }
+    }
+}
+
+/// Best scores of all runs, highest first, saved to `HIGH_SCORES_FILE` in the data directory.
+#[derive(Resource, Debug)]
+pub struct HighScores {
+    pub scores: Vec<u64>,
+    persist: bool,
+}
+
+const HIGH_SCORES_FILE: &str = "high_scores.ron";
+const MAX_HIGH_SCORES: usize = 10;
+
+impl HighScores {
+    /// Returns the rank of `points` in the table, or `None` if it didn't make it.
+    fn insert(&mut self, points: u64) -> Option<usize> {
+        let rank = self.scores.partition_point(|score| *score >= points);
+        if rank >= MAX_HIGH_SCORES {
+            return None;
+        }
+
+        self.scores.insert(rank, points);
+        self.scores.truncate(MAX_HIGH_SCORES);
+        Some(rank)
+    }
+}
+
+fn load_high_scores(mut high_scores: ResMut<HighScores>) {
+    if !high_scores.persist {
+        return;
+    }
+
+    match storage::load::<Vec<u64>>(HIGH_SCORES_FILE) {
+        Ok(Some(mut scores)) => {
+            scores.sort_unstable_by(|a, b| b.cmp(a));
+            scores.truncate(MAX_HIGH_SCORES);
+            high_scores.scores = scores;
+        }
+        Ok(None) => {}
+        Err(err) => warn!("Could not load high scores: {err}"),
+    }
+}
+
+fn reset_scores(mut scores: ResMut<Scores>, count: Res<PlayerCount>) {
+    *scores = Scores(vec![Score::default(); count.0]);
+}
+
+fn decay_combo(mut scores: ResMut<Scores>, time: Res<Time>) {
+    for score in scores.0.iter_mut() {
+        score.decay(time.delta_seconds());
+    }
+}
+
+fn award_kills(
+    mut reader: EventReader<EnemyKilled>,
+    mut scores: ResMut<Scores>,
+    players: Query<(&Player, &Modifiers)>,
+) {
+    for killed in reader.read() {
+        let Some((player, modifiers)) = killed.by.and_then(|by| players.get(by).ok()) else {
+            continue;
+        };
+
+        if let Some(score) = scores.0.get_mut(player.0) {
+            score.add_kill(modifiers.score_multiplier());
+        }
+    }
+}
+
+#[derive(Component)]
+struct ScoreText;
+
+fn spawn_score_text(mut commands: Commands, players: Query<(Entity, &Player)>) {
+    for (entity, player) in players.iter() {
+        commands
+            .spawn((
+                StateScoped(InGame),
+                NodeBundle {
+                    style: Style {
+                        position_type: PositionType::Absolute,
+                        width: Val::Percent(100.),
+                        top: Val::Px(20. + HUD_ROW * player.0 as f32),
+                        justify_content: JustifyContent::Center,
+                        ..Default::default()
+                    },
+                    ..Default::default()
+                },
+            ))
+            .with_children(|parent| {
+                parent.spawn((
+                    ScoreText,
+                    PlayerHud(entity),
+                    TextBundle::from_section(
+                        "",
+                        TextStyle {
+                            font_size: 32.,
+                            color: player.color(),
+                            ..Default::default()
+                        },
+                    ),
+                ));
+            });
+    }
+}
+
+fn update_score_text(
+    scores: Res<Scores>,
+    players: Query<&Player>,
+    mut texts: Query<(&mut Text, &PlayerHud), With<ScoreText>>,
+) {
+    for (mut text, hud) in texts.iter_mut() {
+        let Some(score) = players
+            .get(hud.0)
+            .ok()
+            .and_then(|player| scores.0.get(player.0))
+        else {
+            continue;
+        };
+
+        text.sections[0].value = if score.combo > 1. {
+            format!("{}  x{:.1}", score.points, score.combo)
+        } else {
+            score.points.to_string()
+        };
+    }
+}
+
+/// Adds the run to the high scores and lists them on the game over screen.
+fn record_high_score(
+    mut commands: Commands,
+    scores: Res<Scores>,
+    mut high_scores: ResMut<HighScores>,
+    screens: Query<(Entity, &Screen)>,
+) {
+    let points = scores.total();
+    let rank = high_scores.insert(points);
+    if rank.is_some() && high_scores.persist {
+        if let Err(err) = storage::save(HIGH_SCORES_FILE, &high_scores.scores) {
+            warn!("Could not save high scores: {err}");
+        }
+    }
+
+    let Some((screen, _)) = screens
+        .iter()
+        .find(|(_, screen)| screen.0 == AppState::GameOver)
+    else {
+        return;
+    };
+
+    let heading = match rank {
+        Some(0) => format!("New high score: {points}"),
+        _ => format!("Score: {points}"),
+    };
+
+    let style = |font_size| TextStyle {
+        font_size,
+        ..Default::default()
+    };
+
+    commands.entity(screen).with_children(|parent| {
+        parent.spawn(TextBundle::from_section(heading, style(40.)));
+
+        // Together, the players share a high score, but each sees what they added to it.
+        if scores.0.len() > 1 {
+            let players = scores
+                .0
+                .iter()
+                .enumerate()
+                .map(|(i, score)| format!("P{} {}", i + 1, score.points))
+                .collect::<Vec<_>>()
+                .join("   ");
+            parent.spawn(TextBundle::from_section(players, style(24.)));
+        }
+
+        for (i, points) in high_scores.scores.iter().enumerate() {
+            let mut entry = style(24.);
+            if Some(i) == rank {
+                entry.color = Color::srgb(1., 0.85, 0.2);
+            }
+            parent.spawn(TextBundle::from_section(
+                format!("{}. {}", i + 1, points),
+                entry,
+            ));
+        }
+    });
+}
@@ -0,0 +1,169 @@
+use crate::{collision::Collider, state::AppState};
+use bevy::{prelude::*, utils::HashMap};
+use rand::{rngs::SmallRng, Rng, SeedableRng};
+use std::time::{Duration, Instant};
+
+pub struct SpatialPlugin;
+
+impl Plugin for SpatialPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(SpatialHash::new(SPATIAL_CELL_SIZE))
+            .add_systems(
+                FixedPostUpdate,
+                rebuild_spatial_hash
+                    .after(crate::update_velocity)
+                    .run_if(in_state(AppState::Playing)),
+            );
+    }
+}
+
+/// Roughly the diameter of the largest collider, so that most queries only touch a few cells.
+const SPATIAL_CELL_SIZE: f32 = 100.;
+
+#[derive(Debug, Clone, Copy)]
+struct SpatialEntry {
+    entity: Entity,
+    position: Vec2,
+    radius: f32,
+}
+
+/// Uniform grid of the bounding circle of every `Collider`, rebuilt each fixed step.
+///
+/// Entries are bucketed by their center only, so queries are widened by the largest radius
+/// inserted since the last rebuild.
+#[derive(Resource)]
+pub struct SpatialHash {
+    cell_size: f32,
+    cells: HashMap<IVec2, Vec<SpatialEntry>>,
+    max_radius: f32,
+}
+
+impl SpatialHash {
+    pub fn new(cell_size: f32) -> Self {
+        Self {
+            cell_size,
+            cells: HashMap::default(),
+            max_radius: 0.,
+        }
+    }
+
+    /// Empties every cell while keeping their allocations around for the next rebuild.
+    pub fn clear(&mut self) {
+        self.cells.retain(|_, entries| !entries.is_empty());
+        for entries in self.cells.values_mut() {
+            entries.clear();
+        }
+        self.max_radius = 0.;
+    }
+
+    pub fn insert(&mut self, entity: Entity, position: Vec2, radius: f32) {
+        self.max_radius = self.max_radius.max(radius);
+        self.cells
+            .entry(self.cell(position))
+            .or_default()
+            .push(SpatialEntry {
+                entity,
+                position,
+                radius,
+            });
+    }
+
+    /// Every entity whose bounding circle overlaps the circle at `center` with `radius`.
+    pub fn query(&self, center: Vec2, radius: f32) -> impl Iterator<Item = Entity> + '_ {
+        let reach = radius + self.max_radius;
+        let min = self.cell(center - reach);
+        let max = self.cell(center + reach);
+
+        (min.x..=max.x)
+            .flat_map(move |x| (min.y..=max.y).map(move |y| IVec2::new(x, y)))
+            .filter_map(|cell| self.cells.get(&cell))
+            .flatten()
+            .filter(move |entry| {
+                let reach = radius + entry.radius;
+                entry.position.distance_squared(center) <= reach * reach
+            })
+            .map(|entry| entry.entity)
+    }
+
+    fn cell(&self, position: Vec2) -> IVec2 {
+        (position / self.cell_size).floor().as_ivec2()
+    }
+}
+
+pub fn rebuild_spatial_hash(
+    mut hash: ResMut<SpatialHash>,
+    colliders: Query<(Entity, &Transform, &Collider)>,
+) {
+    hash.clear();
+
+    for (entity, transform, collider) in colliders.iter() {
+        hash.insert(
+            entity,
+            transform.translation.truncate(),
+            collider.shape.bounding_radius(),
+        );
+    }
+}
+
+/// Compares the spatial hash against the old enemies × bullets loop, without opening a window.
+///
+/// Run with `cargo run --release -- --bench-collision`.
+pub fn run_benchmark() {
+    const BULLETS: usize = 10_000;
+    const ENEMIES: usize = 200;
+    const ITERATIONS: u32 = 100;
+    const ARENA: f32 = 4000.;
+    const BULLET_RADIUS: f32 = 10.;
+    const ENEMY_RADIUS: f32 = 40.;
+
+    let mut rng = SmallRng::seed_from_u64(0);
+    let mut position = || {
+        Vec2::new(
+            rng.gen_range(-ARENA / 2.0..ARENA / 2.0),
+            rng.gen_range(-ARENA / 2.0..ARENA / 2.0),
+        )
+    };
+
+    let bullets: Vec<(Entity, Vec2)> = (0..BULLETS)
+        .map(|i| (Entity::from_raw(i as u32), position()))
+        .collect();
+    let enemies: Vec<Vec2> = (0..ENEMIES).map(|_| position()).collect();
+
+    let mut naive_hits = 0;
+    let start = Instant::now();
+    for _ in 0..ITERATIONS {
+        naive_hits = 0;
+        for enemy in enemies.iter() {
+            for (_, bullet) in bullets.iter() {
+                if enemy.distance(*bullet) < ENEMY_RADIUS + BULLET_RADIUS {
+                    naive_hits += 1;
+                }
+            }
+        }
+    }
+    let naive = start.elapsed() / ITERATIONS;
+
+    let mut hash = SpatialHash::new(SPATIAL_CELL_SIZE);
+    let mut hashed_hits = 0;
+    let start = Instant::now();
+    for _ in 0..ITERATIONS {
+        hash.clear();
+        for (entity, bullet) in bullets.iter() {
+            hash.insert(*entity, *bullet, BULLET_RADIUS);
+        }
+
+        hashed_hits = 0;
+        for enemy in enemies.iter() {
+            hashed_hits += hash.query(*enemy, ENEMY_RADIUS).count();
+        }
+    }
+    let hashed = start.elapsed() / ITERATIONS;
+
+    println!("{BULLETS} bullets, {ENEMIES} enemies, averaged over {ITERATIONS} iterations");
+    println!("naive:        {naive:>10?} ({naive_hits} hits)");
+    println!("spatial hash: {hashed:>10?} ({hashed_hits} hits, including rebuild)");
+    println!(
+        "speedup:      {:.1}x",
+        naive.as_secs_f64() / hashed.max(Duration::from_nanos(1)).as_secs_f64()
+    );
+}
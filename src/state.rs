@@ -0,0 +1,209 @@
+use crate::{
+    player::{Dead, Lives, Player, PlayerCount},
+    GameSet,
+};
+use bevy::prelude::*;
+
+pub struct StatePlugin;
+
+impl Plugin for StatePlugin {
+    fn build(&self, app: &mut App) {
+        app.init_state::<AppState>()
+            .init_resource::<RequiredAssets>()
+            .add_computed_state::<InGame>()
+            .enable_state_scoped_entities::<AppState>()
+            .enable_state_scoped_entities::<InGame>()
+            .add_systems(OnEnter(AppState::Title), spawn_title_screen)
+            .add_systems(OnEnter(AppState::Paused), (spawn_pause_screen, pause_time))
+            .add_systems(OnExit(AppState::Paused), unpause_time)
+            .add_systems(OnEnter(AppState::GameOver), spawn_game_over_screen)
+            .add_systems(Update, handle_state_input)
+            .add_systems(
+                FixedUpdate,
+                game_over_on_player_death.in_set(GameSet::Cleanup),
+            );
+    }
+}
+
+#[derive(States, Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum AppState {
+    #[default]
+    Title,
+    Playing,
+    Paused,
+    GameOver,
+    /// Rebinding the controls, from the title screen.
+    Controls,
+}
+
+/// Active during a run, whether or not it is paused.
+///
+/// Systems that set up a run go in `OnEnter(InGame)`, and entities that belong to the run are
+/// despawned on exit with `StateScoped(InGame)`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct InGame;
+
+impl ComputedStates for InGame {
+    type SourceStates = AppState;
+
+    fn compute(sources: AppState) -> Option<Self> {
+        match sources {
+            AppState::Playing | AppState::Paused => Some(Self),
+            AppState::Title | AppState::GameOver | AppState::Controls => None,
+        }
+    }
+}
+
+/// Assets a run can't start without, so that runs always start from the same state.
+#[derive(Resource, Default)]
+pub struct RequiredAssets(Vec<UntypedHandle>);
+
+impl RequiredAssets {
+    pub fn add(&mut self, handle: impl Into<UntypedHandle>) {
+        self.0.push(handle.into());
+    }
+
+    pub fn ready(&self, asset_server: &AssetServer) -> bool {
+        self.0
+            .iter()
+            .all(|handle| asset_server.is_loaded_with_dependencies(handle.id()))
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn handle_state_input(
+    keys: Res<ButtonInput<KeyCode>>,
+    gamepad_buttons: Res<ButtonInput<GamepadButton>>,
+    required: Res<RequiredAssets>,
+    asset_server: Res<AssetServer>,
+    state: Res<State<AppState>>,
+    mut next_state: ResMut<NextState<AppState>>,
+    mut player_count: ResMut<PlayerCount>,
+    mut writer: EventWriter<AppExit>,
+) {
+    // Any connected gamepad can navigate the menus.
+    let pad = |button_type| {
+        gamepad_buttons
+            .get_just_pressed()
+            .any(|button| button.button_type == button_type)
+    };
+
+    let confirm = keys.just_pressed(KeyCode::Enter)
+        || keys.just_pressed(KeyCode::Space)
+        || pad(GamepadButtonType::South)
+        || pad(GamepadButtonType::Start);
+    let back = keys.just_pressed(KeyCode::Escape) || pad(GamepadButtonType::Select);
+    // The second player joins with the gamepad.
+    let coop = keys.just_pressed(KeyCode::Digit2) || pad(GamepadButtonType::North);
+
+    match state.get() {
+        AppState::Title if (confirm || coop) && required.ready(&asset_server) => {
+            *player_count = PlayerCount(if coop { 2 } else { 1 });
+            next_state.set(AppState::Playing)
+        }
+        AppState::Title if keys.just_pressed(KeyCode::KeyC) => next_state.set(AppState::Controls),
+        AppState::Title if back => {
+            writer.send(AppExit::Success);
+        }
+        AppState::Playing if back || pad(GamepadButtonType::Start) => {
+            next_state.set(AppState::Paused)
+        }
+        AppState::Paused if keys.just_pressed(KeyCode::KeyQ) || pad(GamepadButtonType::East) => {
+            next_state.set(AppState::Title)
+        }
+        AppState::Paused if back || confirm => next_state.set(AppState::Playing),
+        AppState::GameOver if confirm => next_state.set(AppState::Playing),
+        AppState::GameOver if back => next_state.set(AppState::Title),
+        _ => {}
+    }
+}
+
+fn game_over_on_player_death(
+    player: Query<(&Lives, Option<&Dead>), With<Player>>,
+    mut next_state: ResMut<NextState<AppState>>,
+) {
+    // `all` holds for no players at all, e.g. before they are spawned.
+    if !player.is_empty()
+        && player
+            .iter()
+            .all(|(lives, dead)| lives.0 == 0 && dead.is_some_and(Dead::finished))
+    {
+        next_state.set(AppState::GameOver);
+    }
+}
+
+fn pause_time(mut time: ResMut<Time<Virtual>>) {
+    time.pause();
+}
+
+fn unpause_time(mut time: ResMut<Time<Virtual>>) {
+    time.unpause();
+}
+
+fn spawn_title_screen(mut commands: Commands) {
+    spawn_screen(
+        &mut commands,
+        AppState::Title,
+        "hypernova",
+        "Enter to start, 2 for two players, C for controls, Esc to quit",
+    );
+}
+
+fn spawn_pause_screen(mut commands: Commands) {
+    spawn_screen(
+        &mut commands,
+        AppState::Paused,
+        "Paused",
+        "Esc to resume, Q to quit to title",
+    );
+}
+
+pub fn spawn_game_over_screen(mut commands: Commands) {
+    spawn_screen(
+        &mut commands,
+        AppState::GameOver,
+        "Game Over",
+        "Enter to restart, Esc to return to title",
+    );
+}
+
+/// Root of the screen shown in a state, more can be added below its subtitle.
+#[derive(Component, Debug, Clone, Copy)]
+pub struct Screen(pub AppState);
+
+/// Spawns a centered title and subtitle that are despawned when leaving `state`.
+pub fn spawn_screen(commands: &mut Commands, state: AppState, title: &str, subtitle: &str) {
+    commands
+        .spawn((
+            Screen(state),
+            StateScoped(state),
+            NodeBundle {
+                style: Style {
+                    width: Val::Percent(100.),
+                    height: Val::Percent(100.),
+                    flex_direction: FlexDirection::Column,
+                    align_items: AlignItems::Center,
+                    justify_content: JustifyContent::Center,
+                    row_gap: Val::Px(20.),
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+        ))
+        .with_children(|parent| {
+            parent.spawn(TextBundle::from_section(
+                title,
+                TextStyle {
+                    font_size: 96.,
+                    ..Default::default()
+                },
+            ));
+            parent.spawn(TextBundle::from_section(
+                subtitle,
+                TextStyle {
+                    font_size: 32.,
+                    ..Default::default()
+                },
+            ));
+        });
+}
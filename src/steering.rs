@@ -0,0 +1,245 @@
+use crate::{
+    player::{nearest_player, Dead, Player},
+    GameSet, Velocity,
+};
+use bevy::{
+    math::cubic_splines::{CubicCardinalSpline, CubicCurve, CubicGenerator},
+    prelude::*,
+};
+use noisy_bevy::simplex_noise_2d;
+use std::f32::consts::TAU;
+
+pub struct SteeringPlugin;
+
+impl Plugin for SteeringPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(
+            FixedUpdate,
+            (chase, orbit, strafe, flee, follow_path, wander).in_set(GameSet::Control),
+        );
+    }
+}
+
+/// How hard the behaviors on this entity may push its `Velocity`.
+///
+/// Every behavior accelerates the entity by up to `acceleration` towards where it wants to go, so
+/// they can be combined. `Friction` is what slows the entity down again.
+#[derive(Component, Debug, Clone, Copy)]
+pub struct Steering {
+    pub acceleration: f32,
+    pub max_speed: f32,
+}
+
+impl Steering {
+    fn steer(&self, velocity: &mut Velocity, direction: Vec2, dt: f32) {
+        velocity.add_velocity_clamped(
+            direction.extend(0.) * self.acceleration * dt,
+            self.max_speed,
+        );
+    }
+}
+
+/// Heads straight for the player.
+#[derive(Component, Debug, Clone, Copy)]
+pub struct Chase;
+
+/// Circles the player at `radius`.
+#[derive(Component, Debug, Clone, Copy)]
+pub struct Orbit {
+    pub radius: f32,
+    pub clockwise: bool,
+}
+
+/// Keeps `distance` from the player while moving sideways, switching direction every `period` seconds.
+#[derive(Component, Debug, Clone)]
+pub struct Strafe {
+    pub distance: f32,
+    timer: Timer,
+    clockwise: bool,
+}
+
+impl Strafe {
+    pub fn new(distance: f32, period: f32) -> Self {
+        Self {
+            distance,
+            timer: Timer::from_seconds(period, TimerMode::Repeating),
+            clockwise: false,
+        }
+    }
+}
+
+/// Runs away from the player while they are within `radius`.
+#[derive(Component, Debug, Clone, Copy)]
+pub struct Flee {
+    pub radius: f32,
+}
+
+/// Follows a Catmull-Rom spline through `points` at `speed` units per second.
+#[derive(Component, Debug, Clone)]
+pub struct FollowPath {
+    pub speed: f32,
+    curve: CubicCurve<Vec2>,
+    looped: bool,
+    t: f32,
+}
+
+impl FollowPath {
+    pub fn new(mut points: Vec<Vec2>, speed: f32, looped: bool) -> Self {
+        if looped && !points.is_empty() {
+            points.push(points[0]);
+        }
+
+        Self {
+            speed,
+            curve: CubicCardinalSpline::new_catmull_rom(points).to_curve(),
+            looped,
+            t: 0.,
+        }
+    }
+}
+
+/// Drifts in a direction that changes smoothly with simplex noise.
+#[derive(Component, Debug, Clone, Copy)]
+pub struct Wander {
+    /// How quickly the direction changes.
+    pub frequency: f32,
+    seed: f32,
+    elapsed: f32,
+}
+
+impl Wander {
+    pub fn new(frequency: f32, seed: f32) -> Self {
+        Self {
+            frequency,
+            seed,
+            elapsed: 0.,
+        }
+    }
+}
+
+/// Pulls an entity at `offset` from its target back to `distance`, from -1 to 1 in strength.
+fn keep_distance(offset: Vec2, distance: f32) -> Vec2 {
+    let strength = ((offset.length() - distance) / distance).clamp(-1., 1.);
+    offset.normalize_or_zero() * strength
+}
+
+fn tangent(offset: Vec2, clockwise: bool) -> Vec2 {
+    let tangent = offset.normalize_or_zero().perp();
+    if clockwise {
+        -tangent
+    } else {
+        tangent
+    }
+}
+
+fn chase(
+    mut entities: Query<(&Transform, &Steering, &mut Velocity), With<Chase>>,
+    players: Query<(&Transform, Has<Dead>), With<Player>>,
+    time: Res<Time>,
+) {
+    for (transform, steering, mut velocity) in entities.iter_mut() {
+        let Some(player) = nearest_player(&players, transform.translation) else {
+            continue;
+        };
+
+        let direction = (player - transform.translation)
+            .truncate()
+            .normalize_or_zero();
+        steering.steer(&mut velocity, direction, time.delta_seconds());
+    }
+}
+
+fn orbit(
+    mut entities: Query<(&Transform, &Steering, &mut Velocity, &Orbit)>,
+    players: Query<(&Transform, Has<Dead>), With<Player>>,
+    time: Res<Time>,
+) {
+    for (transform, steering, mut velocity, orbit) in entities.iter_mut() {
+        let Some(player) = nearest_player(&players, transform.translation) else {
+            continue;
+        };
+
+        let offset = (player - transform.translation).truncate();
+        let direction = (tangent(offset, orbit.clockwise) + keep_distance(offset, orbit.radius))
+            .normalize_or_zero();
+        steering.steer(&mut velocity, direction, time.delta_seconds());
+    }
+}
+
+fn strafe(
+    mut entities: Query<(&Transform, &Steering, &mut Velocity, &mut Strafe)>,
+    players: Query<(&Transform, Has<Dead>), With<Player>>,
+    time: Res<Time>,
+) {
+    for (transform, steering, mut velocity, mut strafe) in entities.iter_mut() {
+        let Some(player) = nearest_player(&players, transform.translation) else {
+            continue;
+        };
+
+        if strafe.timer.tick(time.delta()).just_finished() {
+            strafe.clockwise = !strafe.clockwise;
+        }
+
+        let offset = (player - transform.translation).truncate();
+        let direction = (tangent(offset, strafe.clockwise)
+            + keep_distance(offset, strafe.distance))
+        .normalize_or_zero();
+        steering.steer(&mut velocity, direction, time.delta_seconds());
+    }
+}
+
+fn flee(
+    mut entities: Query<(&Transform, &Steering, &mut Velocity, &Flee)>,
+    players: Query<(&Transform, Has<Dead>), With<Player>>,
+    time: Res<Time>,
+) {
+    for (transform, steering, mut velocity, flee) in entities.iter_mut() {
+        let Some(player) = nearest_player(&players, transform.translation) else {
+            continue;
+        };
+
+        let offset = (transform.translation - player).truncate();
+        if offset.length_squared() < flee.radius * flee.radius {
+            steering.steer(
+                &mut velocity,
+                offset.normalize_or_zero(),
+                time.delta_seconds(),
+            );
+        }
+    }
+}
+
+fn follow_path(
+    mut entities: Query<(&Transform, &Steering, &mut Velocity, &mut FollowPath)>,
+    time: Res<Time>,
+) {
+    for (transform, steering, mut velocity, mut path) in entities.iter_mut() {
+        let segments = path.curve.segments().len() as f32;
+        if segments == 0. {
+            continue;
+        }
+
+        // The curve isn't parameterized by length, so scale by how fast it moves at `t`.
+        let rate = path.curve.velocity(path.t).length().max(1.);
+        path.t += path.speed * time.delta_seconds() / rate;
+        path.t = if path.looped {
+            path.t % segments
+        } else {
+            path.t.min(segments)
+        };
+
+        let target = path.curve.position(path.t);
+        let direction = (target - transform.translation.truncate()).normalize_or_zero();
+        steering.steer(&mut velocity, direction, time.delta_seconds());
+    }
+}
+
+fn wander(mut entities: Query<(&Steering, &mut Velocity, &mut Wander)>, time: Res<Time>) {
+    for (steering, mut velocity, mut wander) in entities.iter_mut() {
+        wander.elapsed += time.delta_seconds();
+
+        let noise = simplex_noise_2d(Vec2::new(wander.elapsed * wander.frequency, wander.seed));
+        let direction = Vec2::from_angle(noise * TAU);
+        steering.steer(&mut velocity, direction, time.delta_seconds());
+    }
+}
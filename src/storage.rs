@@ -0,0 +1,59 @@
+use serde::{de::DeserializeOwned, Serialize};
+use std::{env, fs, io, path::PathBuf};
+use thiserror::Error;
+
+/// Files that outlive a run, like high scores, kept as RON in the user's data directory.
+///
+/// That is `$XDG_DATA_HOME/hypernova` (or `~/.local/share/hypernova`) on Linux,
+/// `~/Library/Application Support/hypernova` on macOS and `%APPDATA%\hypernova` on Windows.
+pub fn data_dir() -> Option<PathBuf> {
+    let dir = if cfg!(target_os = "windows") {
+        env::var_os("APPDATA").map(PathBuf::from)
+    } else if cfg!(target_os = "macos") {
+        env::var_os("HOME").map(|home| PathBuf::from(home).join("Library/Application Support"))
+    } else {
+        env::var_os("XDG_DATA_HOME")
+            .map(PathBuf::from)
+            .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".local/share")))
+    };
+
+    dir.map(|dir| dir.join("hypernova"))
+}
+
+#[derive(Debug, Error)]
+pub enum StorageError {
+    #[error("no data directory, set $HOME")]
+    NoDataDir,
+    #[error("could not access file: {0}")]
+    Io(#[from] io::Error),
+    #[error("could not parse file: {0}")]
+    Parse(#[from] ron::error::SpannedError),
+    #[error("could not write file: {0}")]
+    Write(#[from] ron::Error),
+}
+
+/// Reads `name` from the data directory, or `None` if it was never saved.
+pub fn load<T: DeserializeOwned>(name: &str) -> Result<Option<T>, StorageError> {
+    let path = data_dir().ok_or(StorageError::NoDataDir)?.join(name);
+
+    match fs::read_to_string(path) {
+        Ok(contents) => Ok(Some(ron::from_str(&contents)?)),
+        Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(None),
+        Err(err) => Err(err.into()),
+    }
+}
+
+/// Writes `name` to the data directory.
+///
+/// The file is written next to the old one and then renamed over it, so that a crash mid-write
+/// leaves the old file intact.
+pub fn save<T: Serialize>(name: &str, value: &T) -> Result<(), StorageError> {
+    let dir = data_dir().ok_or(StorageError::NoDataDir)?;
+    fs::create_dir_all(&dir)?;
+
+    let contents = ron::ser::to_string_pretty(value, ron::ser::PrettyConfig::default())?;
+    let temp = dir.join(format!("{name}.tmp"));
+    fs::write(&temp, contents)?;
+    fs::rename(temp, dir.join(name))?;
+    Ok(())
+}
@@ -0,0 +1,272 @@
+use crate::{
+    enemy::{spawn_enemy, Enemy},
+    player::{Dead, Player},
+    rng::GameRng,
+    ron_asset::{RonAsset, RonAssetApp},
+    state::{AppState, InGame, RequiredAssets},
+    GameSet, ARENA,
+};
+use bevy::prelude::*;
+use rand::Rng;
+use serde::Deserialize;
+use std::f32::consts::TAU;
+
+pub struct WavePlugin;
+
+impl Plugin for WavePlugin {
+    fn build(&self, app: &mut App) {
+        app.init_ron_asset::<WaveScript>()
+            .add_event::<WaveCompleted>()
+            .add_systems(Startup, load_wave_script)
+            .add_systems(OnEnter(InGame), (init_wave_director, spawn_wave_text))
+            .add_systems(FixedUpdate, update_wave_director.in_set(GameSet::Control))
+            .add_systems(Update, update_wave_text.run_if(in_state(AppState::Playing)));
+    }
+}
+
+/// A run as written by designers in a `.waves.ron` file.
+///
+/// The script loops back to the first wave after the last one, at a higher difficulty.
+#[derive(Asset, TypePath, Debug, Deserialize)]
+pub struct WaveScript {
+    pub waves: Vec<Wave>,
+    /// Added to the difficulty every time a wave is completed.
+    pub difficulty_ramp: f32,
+}
+
+impl RonAsset for WaveScript {
+    const EXTENSIONS: &'static [&'static str] = &["waves.ron"];
+
+    /// Every wave must spawn at least one enemy, otherwise it would complete on every fixed step.
+    fn validate(&self) -> Result<(), String> {
+        if self.difficulty_ramp < 0. {
+            return Err("`difficulty_ramp` can't be negative".into());
+        }
+
+        for (i, wave) in self.waves.iter().enumerate() {
+            if wave.groups.is_empty() {
+                return Err(format!("wave {i} has no groups"));
+            }
+            if wave.groups.iter().any(|group| group.count == 0) {
+                return Err(format!("wave {i} has a group with a `count` of 0"));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Completed once every group has spawned and every enemy is dead.
+#[derive(Debug, Deserialize)]
+pub struct Wave {
+    pub groups: Vec<SpawnGroup>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SpawnGroup {
+    /// Number of enemies at a difficulty of 1, scaled up as the difficulty ramps. At least 1.
+    pub count: u32,
+    /// Seconds after the group starts before its first enemy spawns.
+    #[serde(default)]
+    pub delay: f32,
+    /// Seconds between each enemy of the group.
+    #[serde(default)]
+    pub interval: f32,
+    /// Hold the group back until every group before it has spawned and all enemies are dead.
+    #[serde(default)]
+    pub when_cleared: bool,
+}
+
+/// Enemies spawn on a circle of this radius around the players, just outside of the screen, or
+/// at the edge of the `ARENA` when the circle leaves it.
+const SPAWN_DISTANCE: f32 = 1100.;
+
+/// Sent when a wave is completed, before the next one starts.
+#[derive(Event, Debug, Clone, Copy)]
+pub struct WaveCompleted {
+    /// Number of waves completed so far, including this one.
+    pub wave: u32,
+}
+
+/// Spawns enemies by following the `WaveScript`.
+#[derive(Resource)]
+pub struct WaveDirector {
+    script: Handle<WaveScript>,
+    /// Number of waves completed this run.
+    pub completed: u32,
+    /// Scales the number, health and fire rate of spawned enemies.
+    pub difficulty: f32,
+    elapsed: f32,
+    groups: Vec<GroupState>,
+}
+
+#[derive(Default, Clone)]
+struct GroupState {
+    started_at: Option<f32>,
+    spawned: u32,
+}
+
+impl WaveDirector {
+    pub fn new(script: Handle<WaveScript>) -> Self {
+        Self {
+            script,
+            completed: 0,
+            difficulty: 1.,
+            elapsed: 0.,
+            groups: Vec::new(),
+        }
+    }
+}
+
+const WAVE_SCRIPT: &str = "waves/default.waves.ron";
+
+fn load_wave_script(asset_server: Res<AssetServer>, mut required: ResMut<RequiredAssets>) {
+    required.add(asset_server.load::<WaveScript>(WAVE_SCRIPT));
+}
+
+fn init_wave_director(mut commands: Commands, asset_server: Res<AssetServer>) {
+    commands.insert_resource(WaveDirector::new(asset_server.load(WAVE_SCRIPT)));
+}
+
+#[allow(clippy::too_many_arguments)]
+fn update_wave_director(
+    mut commands: Commands,
+    mut director: ResMut<WaveDirector>,
+    scripts: Res<Assets<WaveScript>>,
+    enemies: Query<(), With<Enemy>>,
+    player: Query<&Transform, (With<Player>, Without<Dead>)>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    asset_server: Res<AssetServer>,
+    time: Res<Time>,
+    mut rng: ResMut<GameRng>,
+    mut writer: EventWriter<WaveCompleted>,
+) {
+    let Some(script) = scripts.get(&director.script) else {
+        return;
+    };
+
+    if script.waves.is_empty() {
+        return;
+    }
+
+    let director = &mut *director;
+    let wave = &script.waves[director.completed as usize % script.waves.len()];
+    director
+        .groups
+        .resize(wave.groups.len(), GroupState::default());
+    director.elapsed += time.delta_seconds();
+
+    // Spawn around the players still in play, dead ones waiting to be revived would pull the
+    // ring away from the action.
+    let center = if player.is_empty() {
+        Vec3::ZERO
+    } else {
+        player.iter().map(|player| player.translation).sum::<Vec3>() / player.iter().len() as f32
+    };
+    let mut cleared = enemies.is_empty();
+    let mut spawned_all = true;
+
+    for (group, state) in wave.groups.iter().zip(director.groups.iter_mut()) {
+        if state.started_at.is_none() && (!group.when_cleared || (spawned_all && cleared)) {
+            state.started_at = Some(director.elapsed);
+        }
+
+        let Some(started_at) = state.started_at else {
+            spawned_all = false;
+            continue;
+        };
+
+        let count = (group.count as f32 * director.difficulty).ceil() as u32;
+        while state.spawned < count
+            && director.elapsed >= started_at + group.delay + state.spawned as f32 * group.interval
+        {
+            let angle = rng.gameplay.gen_range(0.0..TAU);
+            spawn_enemy(
+                &mut commands,
+                &mut meshes,
+                &asset_server,
+                &mut rng.gameplay,
+                (center.truncate() + Vec2::from_angle(angle) * SPAWN_DISTANCE)
+                    .clamp(ARENA.min, ARENA.max)
+                    .extend(center.z),
+                director.difficulty,
+            );
+            state.spawned += 1;
+            cleared = false;
+        }
+
+        spawned_all &= state.spawned >= count;
+    }
+
+    if spawned_all && cleared {
+        director.completed += 1;
+        director.difficulty += script.difficulty_ramp;
+        director.elapsed = 0.;
+        director.groups.clear();
+
+        writer.send(WaveCompleted {
+            wave: director.completed,
+        });
+    }
+}
+
+#[derive(Component)]
+struct WaveText;
+
+fn spawn_wave_text(mut commands: Commands) {
+    commands.spawn((
+        WaveText,
+        StateScoped(InGame),
+        TextBundle::from_section(
+            "Wave 1",
+            TextStyle {
+                font_size: 32.,
+                ..Default::default()
+            },
+        )
+        .with_style(Style {
+            position_type: PositionType::Absolute,
+            left: Val::Px(20.),
+            bottom: Val::Px(20.),
+            ..Default::default()
+        }),
+    ));
+}
+
+/// Shows the wave being fought, the one after the last completed.
+fn update_wave_text(
+    mut reader: EventReader<WaveCompleted>,
+    mut texts: Query<&mut Text, With<WaveText>>,
+) {
+    let Some(completed) = reader.read().last() else {
+        return;
+    };
+
+    for mut text in texts.iter_mut() {
+        text.sections[0].value = format!("Wave {}", completed.wave + 1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn validate(script: &str) -> Result<(), String> {
+        ron::de::from_str::<WaveScript>(script).unwrap().validate()
+    }
+
+    #[test]
+    fn default_script_is_valid() {
+        assert_eq!(
+            validate(include_str!("../assets/waves/default.waves.ron")),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn rejects_waves_without_enemies() {
+        assert!(validate("(difficulty_ramp: 0.25, waves: [(groups: [])])").is_err());
+        assert!(validate("(difficulty_ramp: 0.25, waves: [(groups: [(count: 0)])])").is_err());
+        assert!(validate("(difficulty_ramp: -1.0, waves: [(groups: [(count: 1)])])").is_err());
+    }
+}
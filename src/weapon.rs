@@ -0,0 +1,395 @@
+use crate::{
+    bullet::BulletType,
+    player::{spawn_players, BufferedInput, Player, PlayerHud, HUD_ROW},
+    state::{AppState, InGame},
+    GameSet,
+};
+use bevy::prelude::*;
+use leafwing_input_manager::prelude::*;
+use std::time::Duration;
+
+pub struct WeaponPlugin;
+
+impl Plugin for WeaponPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_plugins(InputManagerPlugin::<WeaponAction>::default())
+            .add_systems(OnEnter(InGame), spawn_weapon_text.after(spawn_players))
+            .add_systems(
+                FixedUpdate,
+                (cool_weapons, cycle_weapons)
+                    .chain()
+                    .in_set(GameSet::Control),
+            )
+            .add_systems(
+                Update,
+                update_weapon_text.run_if(in_state(AppState::Playing)),
+            );
+    }
+}
+
+#[derive(Debug, Actionlike, PartialEq, Eq, Hash, Clone, Copy, Reflect)]
+pub enum WeaponAction {
+    Previous,
+    Next,
+}
+
+/// A gun the player can hold, spawned as a child of the player and listed in its `WeaponInventory`.
+///
+/// Only the current weapon fires, but every weapon cools down and reloads while holstered.
+#[derive(Component, Debug, Clone)]
+pub struct Weapon {
+    pub name: &'static str,
+    pub ty: BulletType,
+    /// Shots per second.
+    pub fire_rate: f32,
+    /// Bullets per shot.
+    pub projectiles: u32,
+    /// Radians the bullets of a shot are fanned across.
+    pub spread: f32,
+    /// Velocity the player is pushed back with on every shot.
+    pub recoil: f32,
+    /// Keeps firing while the button is held, instead of once per press.
+    pub automatic: bool,
+    pub ammo: Option<Ammo>,
+    pub heat: Option<Heat>,
+    /// Seconds until the next shot, below zero when it is overdue.
+    cooldown: f32,
+}
+
+/// Rounds in the magazine, refilled once it is empty.
+#[derive(Debug, Clone)]
+pub struct Ammo {
+    pub rounds: u32,
+    pub capacity: u32,
+    reload: Timer,
+}
+
+impl Ammo {
+    pub fn reloading(&self) -> bool {
+        self.rounds == 0
+    }
+}
+
+/// Builds up with every shot. Once full, the weapon can't fire until it has completely cooled down.
+#[derive(Debug, Clone)]
+pub struct Heat {
+    /// Between 0 and 1.
+    pub current: f32,
+    pub overheated: bool,
+    per_shot: f32,
+    /// Per second.
+    cooling: f32,
+}
+
+impl Weapon {
+    pub fn new(name: &'static str, ty: BulletType, fire_rate: f32) -> Self {
+        Self {
+            name,
+            ty,
+            fire_rate,
+            projectiles: 1,
+            spread: 0.,
+            recoil: 0.,
+            automatic: false,
+            ammo: None,
+            heat: None,
+            cooldown: 0.,
+        }
+    }
+
+    pub fn with_projectiles(mut self, projectiles: u32, spread: f32) -> Self {
+        self.projectiles = projectiles;
+        self.spread = spread;
+        self
+    }
+
+    pub fn with_recoil(mut self, recoil: f32) -> Self {
+        self.recoil = recoil;
+        self
+    }
+
+    pub fn automatic(mut self) -> Self {
+        self.automatic = true;
+        self
+    }
+
+    /// Holds `capacity` rounds and takes `reload` seconds to refill once empty.
+    pub fn with_ammo(mut self, capacity: u32, reload: f32) -> Self {
+        self.ammo = Some(Ammo {
+            rounds: capacity,
+            capacity,
+            reload: Timer::from_seconds(reload, TimerMode::Once),
+        });
+        self
+    }
+
+    /// Every shot adds `per_shot` heat, and `cooling` heat is lost per second.
+    pub fn with_heat(mut self, per_shot: f32, cooling: f32) -> Self {
+        self.heat = Some(Heat {
+            current: 0.,
+            overheated: false,
+            per_shot,
+            cooling,
+        });
+        self
+    }
+
+    /// While the trigger is `held`, the cooldown runs up to a step past zero, so that the next
+    /// shot makes up for the time the last one waited on the fixed step.
+    fn cool(&mut self, delta: Duration, held: bool) {
+        let overdue = if held { delta.as_secs_f32() } else { 0. };
+        self.cooldown = (self.cooldown - delta.as_secs_f32()).max(-overdue);
+
+        if let Some(ammo) = &mut self.ammo {
+            if ammo.reloading() && ammo.reload.tick(delta).finished() {
+                ammo.rounds = ammo.capacity;
+                ammo.reload.reset();
+            }
+        }
+
+        if let Some(heat) = &mut self.heat {
+            heat.current = (heat.current - heat.cooling * delta.as_secs_f32()).max(0.);
+            if heat.current == 0. {
+                heat.overheated = false;
+            }
+        }
+    }
+
+    pub fn ready(&self) -> bool {
+        self.cooldown <= 0.
+            && !self.ammo.as_ref().is_some_and(Ammo::reloading)
+            && !self.heat.as_ref().is_some_and(|heat| heat.overheated)
+    }
+
+    /// Spends a shot. Check `ready` first.
+    ///
+    /// `rate` scales the fire rate, e.g. a `rate` of 2 allows the next shot twice as soon.
+    pub fn fire(&mut self, rate: f32) {
+        self.cooldown += 1. / (self.fire_rate * rate);
+
+        if let Some(ammo) = &mut self.ammo {
+            ammo.rounds = ammo.rounds.saturating_sub(1);
+        }
+
+        if let Some(heat) = &mut self.heat {
+            heat.current = (heat.current + heat.per_shot).min(1.);
+            if heat.current >= 1. {
+                heat.overheated = true;
+            }
+        }
+    }
+
+    /// Directions of the bullets of a single shot, fanned around `aim`.
+    ///
+    /// Every `extra` bullet widens the fan, so that it doesn't overlap the others. The whole fan
+    /// is then scaled by `narrow`.
+    pub fn directions(&self, aim: Vec2, extra: u32, narrow: f32) -> impl Iterator<Item = Vec2> {
+        let projectiles = self.projectiles + extra;
+        let spread = (self.spread + EXTRA_PROJECTILE_SPREAD * extra as f32) * narrow;
+        let step = if projectiles > 1 {
+            spread / (projectiles - 1) as f32
+        } else {
+            0.
+        };
+
+        (0..projectiles).map(move |i| {
+            let angle = -spread / 2. + step * i as f32;
+            Vec2::from_angle(angle).rotate(aim)
+        })
+    }
+}
+
+/// Radians added to the spread of a weapon for every extra bullet.
+const EXTRA_PROJECTILE_SPREAD: f32 = 0.12;
+
+/// The weapons a player holds, in the order they are cycled through.
+#[derive(Component, Debug, Clone)]
+pub struct WeaponInventory {
+    weapons: Vec<Entity>,
+    current: usize,
+}
+
+impl WeaponInventory {
+    pub fn current(&self) -> Option<Entity> {
+        self.weapons.get(self.current).copied()
+    }
+
+    /// Moves `by` weapons forward, or backward when negative, wrapping around.
+    pub fn cycle(&mut self, by: i32) {
+        if self.weapons.is_empty() {
+            return;
+        }
+
+        let len = self.weapons.len() as i32;
+        self.current = (self.current as i32 + by).rem_euclid(len) as usize;
+    }
+}
+
+/// Gives `player` the starting weapons.
+pub fn spawn_loadout(commands: &mut Commands, asset_server: &AssetServer, player: Entity) {
+    let ball = BulletType::named(asset_server, "ball");
+    let needle = BulletType::named(asset_server, "needle");
+
+    let loadout = [
+        Weapon::new("Blaster", ball.clone(), 6.).with_recoil(1000.),
+        Weapon::new("Rifle", needle, 12.)
+            .automatic()
+            .with_recoil(100.)
+            .with_heat(0.06, 0.5),
+        Weapon::new("Shotgun", ball, 1.5)
+            .with_projectiles(7, 0.6)
+            .with_recoil(1500.)
+            .with_ammo(6, 1.5),
+    ];
+
+    let mut weapons = Vec::with_capacity(loadout.len());
+    commands.entity(player).with_children(|parent| {
+        for weapon in loadout {
+            weapons.push(parent.spawn(weapon).id());
+        }
+    });
+    commands.entity(player).insert(WeaponInventory {
+        weapons,
+        current: 0,
+    });
+}
+
+fn cool_weapons(
+    players: Query<(&BufferedInput, &WeaponInventory), With<Player>>,
+    mut weapons: Query<(Entity, &mut Weapon)>,
+    time: Res<Time>,
+) {
+    let held: Vec<Entity> = players
+        .iter()
+        .filter(|(input, _)| input.firing)
+        .filter_map(|(_, inventory)| inventory.current())
+        .collect();
+
+    for (entity, mut weapon) in weapons.iter_mut() {
+        weapon.cool(time.delta(), held.contains(&entity));
+    }
+}
+
+pub fn cycle_weapons(mut player: Query<(&mut BufferedInput, &mut WeaponInventory), With<Player>>) {
+    for (mut input, mut inventory) in player.iter_mut() {
+        let by = std::mem::take(&mut input.cycle);
+        inventory.cycle(by);
+    }
+}
+
+#[derive(Component)]
+struct WeaponText;
+
+fn spawn_weapon_text(mut commands: Commands, players: Query<(Entity, &Player)>) {
+    for (entity, player) in players.iter() {
+        commands.spawn((
+            WeaponText,
+            PlayerHud(entity),
+            StateScoped(InGame),
+            TextBundle::from_section(
+                "",
+                TextStyle {
+                    font_size: 32.,
+                    color: player.color(),
+                    ..Default::default()
+                },
+            )
+            .with_style(Style {
+                position_type: PositionType::Absolute,
+                right: Val::Px(20.),
+                bottom: Val::Px(20. + HUD_ROW * player.0 as f32),
+                ..Default::default()
+            }),
+        ));
+    }
+}
+
+fn update_weapon_text(
+    players: Query<&WeaponInventory>,
+    weapons: Query<&Weapon>,
+    mut texts: Query<(&mut Text, &PlayerHud), With<WeaponText>>,
+) {
+    for (mut text, hud) in texts.iter_mut() {
+        let Some(weapon) = players
+            .get(hud.0)
+            .ok()
+            .and_then(WeaponInventory::current)
+            .and_then(|entity| weapons.get(entity).ok())
+        else {
+            continue;
+        };
+
+        text.sections[0].value = weapon_status(weapon);
+    }
+}
+
+fn weapon_status(weapon: &Weapon) -> String {
+    let mut value = weapon.name.to_string();
+    if let Some(ammo) = &weapon.ammo {
+        if ammo.reloading() {
+            value += "  reloading";
+        } else {
+            value += &format!("  {}/{}", ammo.rounds, ammo.capacity);
+        }
+    }
+    if let Some(heat) = &weapon.heat {
+        if heat.overheated {
+            value += "  overheated";
+        } else {
+            value += &format!("  heat {:.0}%", heat.current * 100.);
+        }
+    }
+
+    value
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const STEP: Duration = Duration::from_nanos(1_000_000_000 / 60);
+
+    fn weapon(fire_rate: f32) -> Weapon {
+        Weapon::new("test", BulletType(Handle::default()), fire_rate).automatic()
+    }
+
+    /// Holds the trigger for `steps` fixed steps, and returns the number of shots.
+    fn hold(weapon: &mut Weapon, steps: u32) -> u32 {
+        let mut shots = 0;
+        for _ in 0..steps {
+            weapon.cool(STEP, true);
+            if weapon.ready() {
+                weapon.fire(1.);
+                shots += 1;
+            }
+        }
+        shots
+    }
+
+    #[test]
+    fn fires_at_its_fire_rate() {
+        // Neither rate is a whole number of steps per shot.
+        for fire_rate in [7., 25.] {
+            let shots = hold(&mut weapon(fire_rate), 60 * 20);
+            let rate = shots as f32 / 20.;
+            assert!(
+                (rate - fire_rate).abs() < 0.1,
+                "{shots} shots in 20 seconds at a fire rate of {fire_rate}"
+            );
+        }
+    }
+
+    #[test]
+    fn doesnt_bank_shots() {
+        let mut weapon = weapon(7.);
+        weapon.cool(Duration::from_secs(5), false);
+        assert_eq!(hold(&mut weapon, 2), 1);
+
+        // Held without firing, e.g. while dead.
+        let mut weapon = self::weapon(7.);
+        for _ in 0..60 * 5 {
+            weapon.cool(STEP, true);
+        }
+        assert_eq!(hold(&mut weapon, 2), 1);
+    }
+}